@@ -1,24 +1,353 @@
 // Based on the Getting Started Processing tutorial:
 // https://py.processing.org/tutorials/gettingstarted/
+//
+// Grew into a small drawing toy. Hold the left mouse button to paint with the ellipse.
+
+use std::f32::consts::TAU;
 
 use nannou::prelude::*;
+use nannou::wgpu;
+use nannou::window::Window;
+use nannou::winit;
+use nannou::Draw;
+use nannou_sketches::processing::Processing;
 
-fn view(app: &App, frame: Frame) {
-    // Begin drawing
-    let draw = app.draw();
+const WIDTH: f32 = 800.0;
+const HEIGHT: f32 = 600.0;
+
+// Processing's default background.
+const BG_COLOR: f32 = 0.8;
+
+const BRUSH_RADIUS: f32 = 80.0;
+const MIN_BRUSH_RADIUS: f32 = 2.0;
+const MAX_BRUSH_RADIUS: f32 = 200.0;
+const MAX_STROKE_WEIGHT: f32 = 20.0;
 
-    // Draw an ellipse to follow the mouse.
-    let color = if app.mouse.buttons.left().is_down() {
-        BLACK
+// Stamps are placed this fraction of the radius apart, so fast strokes don't leave gaps.
+const STAMP_SPACING: f32 = 0.25;
+// In size by speed mode, the radius at a mouse speed of one pixel per second.
+const SPEED_RADIUS_SCALE: f32 = 0.05;
+// How quickly the brush size catches up to the mouse speed. Higher is snappier.
+const SPEED_SMOOTHING: f32 = 10.0;
+// In turns per second.
+const RAINBOW_SPEED: f32 = 0.2;
+// Kaleidoscope segment counts to cycle through. One segment is no mirroring.
+const SEGMENT_COUNTS: &[usize] = &[1, 2, 3, 4, 6, 8, 12];
+
+// Colors to cycle through for fill and stroke. None disables it, like noFill() and noStroke().
+const COLOR_CHOICES: &[Option<(f32, f32, f32)>] = &[
+    Some((1.0, 1.0, 1.0)),
+    Some((0.0, 0.0, 0.0)),
+    None,
+];
+
+// How each stamp is drawn, like Processing's fill(), stroke() and strokeWeight().
+#[derive(Clone, Copy, Debug)]
+struct Style {
+    fill: Option<Rgba>,
+    stroke: Option<Rgba>,
+    stroke_weight: f32,
+}
+
+impl Default for Style {
+    // Processing's defaults: white fill with a thin black outline.
+    fn default() -> Self {
+        Self {
+            fill: Some(rgba(1.0, 1.0, 1.0, 1.0)),
+            stroke: Some(rgba(0.0, 0.0, 0.0, 1.0)),
+            stroke_weight: 1.0,
+        }
+    }
+}
+
+impl Style {
+    // Replaces the hue of the fill, or of the stroke if there's no fill.
+    fn with_hue(self, hue: f32) -> Self {
+        let color = |alpha| {
+            let rgb: Rgb = hsv(hue, 0.6, 1.0).into();
+            rgba(rgb.red, rgb.green, rgb.blue, alpha)
+        };
+        match (self.fill, self.stroke) {
+            (Some(fill), _) => Self {
+                fill: Some(color(fill.alpha)),
+                ..self
+            },
+            (None, Some(stroke)) => Self {
+                stroke: Some(color(stroke.alpha)),
+                ..self
+            },
+            (None, None) => self,
+        }
+    }
+}
+
+// Paints a circle for each position, like calling ellipse() in Processing.
+fn draw_ellipses(draw: &Draw, positions: &[Vec2], radius: f32, style: Style) {
+    let mut p = Processing::new(draw);
+    match style.fill {
+        Some(fill) => p.fill(fill),
+        None => p.no_fill(),
+    }
+    match style.stroke {
+        Some(stroke) => p.stroke(stroke),
+        None => p.no_stroke(),
+    }
+    p.stroke_weight(style.stroke_weight);
+    for pos in positions {
+        p.circle(pos.x, pos.y, radius * 2.0);
+    }
+}
+
+// Cycles to the next entry of COLOR_CHOICES after the given color.
+fn next_color(color: Option<Rgba>) -> Option<Rgba> {
+    let index = COLOR_CHOICES.iter()
+        .position(|&choice| match (choice, color) {
+            (Some((r, g, b)), Some(color)) => (r, g, b) == (color.red, color.green, color.blue),
+            (None, None) => true,
+            _ => false,
+        })
+        .map_or(0, |i| i + 1);
+    COLOR_CHOICES[index % COLOR_CHOICES.len()].map(|(r, g, b)| rgba(r, g, b, 1.0))
+}
+
+// All the places a stamp at pos shows up with the given number of kaleidoscope segments. Each
+// segment is rotated around the center of the window and gets both the stamp and its mirror image,
+// so there are two stamps per segment.
+fn kaleidoscope(pos: Vec2, segments: usize) -> Vec<Vec2> {
+    if segments <= 1 {
+        return vec![pos];
+    }
+    let mirrored = vec2(pos.x, -pos.y);
+    (0..segments)
+        .flat_map(|i| {
+            let angle = i as f32 * TAU / segments as f32;
+            [pos.rotate(angle), mirrored.rotate(angle)]
+        })
+        .collect()
+}
+
+// What we've painted so far. Kept in a texture so strokes stick around between frames.
+struct Canvas {
+    texture: wgpu::Texture,
+    draw: Draw,
+    renderer: nannou::draw::Renderer,
+    reshaper: wgpu::TextureReshaper,
+    // Clear to the background on the next render.
+    needs_clear: bool,
+}
+
+impl Canvas {
+    fn new(window: &Window) -> Self {
+        let device = window.device();
+        let sample_count = window.msaa_samples();
+        let texture = wgpu::TextureBuilder::new()
+            .size([WIDTH as u32, HEIGHT as u32])
+            .usage(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
+            .sample_count(sample_count)
+            .format(Frame::TEXTURE_FORMAT)
+            .build(device);
+        let renderer = nannou::draw::RendererBuilder::new()
+            .build_from_texture_descriptor(device, texture.descriptor());
+        let texture_view = texture.view().build();
+        let reshaper = wgpu::TextureReshaper::new(
+            device,
+            &texture_view,
+            sample_count,
+            texture.sample_type(),
+            sample_count,
+            Frame::TEXTURE_FORMAT,
+        );
+
+        Self {
+            texture,
+            draw: Draw::new(),
+            renderer,
+            reshaper,
+            needs_clear: true,
+        }
+    }
+
+    // Paints a stamp at each of the given positions and renders them to the texture.
+    fn paint(&mut self, window: &Window, stamps: &[Vec2], radius: f32, style: Style) {
+        let draw = &self.draw;
+        draw.reset();
+
+        if self.needs_clear {
+            draw.background().color(rgb(BG_COLOR, BG_COLOR, BG_COLOR));
+            self.needs_clear = false;
+        }
+        draw_ellipses(draw, stamps, radius, style);
+
+        let device = window.device();
+        let ce_desc = wgpu::CommandEncoderDescriptor {
+            label: Some("canvas renderer"),
+        };
+        let mut encoder = device.create_command_encoder(&ce_desc);
+        self.renderer.render_to_texture(device, &mut encoder, draw, &self.texture);
+        window.queue().submit(Some(encoder.finish()));
+    }
+}
+
+struct Model {
+    canvas: Canvas,
+    style: Style,
+    radius: f32,
+
+    // Brush modes. These all stack.
+    size_by_speed: bool,
+    rainbow: bool,
+    segments: usize,
+
+    // Where the mouse was last frame while painting, to fill in the stroke between frames.
+    last_mouse: Option<Vec2>,
+    // Smoothed mouse speed in pixels per second.
+    mouse_speed: f32,
+    hue: f32,
+}
+
+impl Model {
+    // The brush radius, taking size by speed into account.
+    fn brush_radius(&self) -> f32 {
+        if self.size_by_speed {
+            clamp(self.mouse_speed * SPEED_RADIUS_SCALE * self.radius / BRUSH_RADIUS, MIN_BRUSH_RADIUS, self.radius)
+        } else {
+            self.radius
+        }
+    }
+
+    fn brush_style(&self) -> Style {
+        if self.rainbow {
+            self.style.with_hue(self.hue)
+        } else {
+            self.style
+        }
+    }
+}
+
+fn model(app: &App) -> Model {
+    let window_builder = winit::window::WindowBuilder::new()
+        .with_resizable(false);
+    let window_id = app.new_window()
+        .window(window_builder)
+        .size_pixels(WIDTH as u32, HEIGHT as u32)
+        .title("Mouse Ellipse")
+        .event(event)
+        .view(view)
+        .build()
+        .unwrap();
+    let window = app.window(window_id).unwrap();
+
+    Model {
+        canvas: Canvas::new(&window),
+        style: Style::default(),
+        radius: BRUSH_RADIUS,
+
+        size_by_speed: false,
+        rainbow: false,
+        segments: 1,
+
+        last_mouse: None,
+        mouse_speed: 0.0,
+        hue: 0.0,
+    }
+}
+
+fn event(_app: &App, model: &mut Model, event: WindowEvent) {
+    match event {
+        WindowEvent::KeyPressed(Key::F) => {
+            model.style.fill = next_color(model.style.fill);
+            println!("Fill: {:?}", model.style.fill);
+        }
+        WindowEvent::KeyPressed(Key::S) => {
+            model.style.stroke = next_color(model.style.stroke);
+            println!("Stroke: {:?}", model.style.stroke);
+        }
+        WindowEvent::KeyPressed(Key::RBracket) => {
+            model.style.stroke_weight = (model.style.stroke_weight + 1.0).min(MAX_STROKE_WEIGHT);
+            println!("Stroke weight: {}", model.style.stroke_weight);
+        }
+        WindowEvent::KeyPressed(Key::LBracket) => {
+            model.style.stroke_weight = (model.style.stroke_weight - 1.0).max(1.0);
+            println!("Stroke weight: {}", model.style.stroke_weight);
+        }
+        WindowEvent::KeyPressed(Key::Up) => {
+            model.radius = (model.radius * 1.25).min(MAX_BRUSH_RADIUS);
+            println!("Brush radius: {:.1}", model.radius);
+        }
+        WindowEvent::KeyPressed(Key::Down) => {
+            model.radius = (model.radius / 1.25).max(MIN_BRUSH_RADIUS);
+            println!("Brush radius: {:.1}", model.radius);
+        }
+        WindowEvent::KeyPressed(Key::V) => {
+            model.size_by_speed = !model.size_by_speed;
+            println!("Size by speed: {}", model.size_by_speed);
+        }
+        WindowEvent::KeyPressed(Key::H) => {
+            model.rainbow = !model.rainbow;
+            println!("Rainbow: {}", model.rainbow);
+        }
+        WindowEvent::KeyPressed(Key::K) => {
+            let index = SEGMENT_COUNTS.iter().position(|&n| n == model.segments).unwrap_or(0);
+            model.segments = SEGMENT_COUNTS[(index + 1) % SEGMENT_COUNTS.len()];
+            println!("Kaleidoscope segments: {}", model.segments);
+        }
+        WindowEvent::KeyPressed(Key::C) => {
+            model.canvas.needs_clear = true;
+        }
+        _ => {}
+    }
+}
+
+fn update(app: &App, model: &mut Model, update: Update) {
+    let dt = update.since_last.as_secs_f32();
+    let mouse = app.mouse.position();
+
+    let speed = match model.last_mouse {
+        Some(last) if dt > 0.0 => mouse.distance(last) / dt,
+        _ => 0.0,
+    };
+    model.mouse_speed += (speed - model.mouse_speed) * (SPEED_SMOOTHING * dt).min(1.0);
+
+    // Paint stamps along the path the mouse took since last frame.
+    let mut stamps = Vec::new();
+    if app.mouse.buttons.left().is_down() {
+        let radius = model.brush_radius();
+        let from = model.last_mouse.unwrap_or(mouse);
+        let spacing = (radius * STAMP_SPACING).max(1.0);
+        let count = (from.distance(mouse) / spacing).ceil().max(1.0) as usize;
+        for i in 1..=count {
+            let pos = from.lerp(mouse, i as f32 / count as f32);
+            stamps.extend(kaleidoscope(pos, model.segments));
+        }
+        model.last_mouse = Some(mouse);
+        if model.rainbow {
+            model.hue = (model.hue + RAINBOW_SPEED * dt).rem_euclid(1.0);
+        }
     } else {
-        WHITE
+        model.last_mouse = None;
+    }
+
+    // Always render so a clear shows up even when we're not painting.
+    let (radius, style) = (model.brush_radius(), model.brush_style());
+    model.canvas.paint(&app.main_window(), &stamps, radius, style);
+}
+
+fn view(app: &App, model: &Model, frame: Frame) {
+    // Copy over what we've painted so far.
+    {
+        let mut encoder = frame.command_encoder();
+        model.canvas.reshaper.encode_render_pass(frame.texture_view(), &mut encoder);
+    }
+
+    // Preview the brush under the mouse without painting it.
+    let draw = app.draw();
+    let radius = model.brush_radius();
+    let style = Style {
+        fill: None,
+        stroke: Some(rgba(0.0, 0.0, 0.0, 0.5)),
+        stroke_weight: 1.0,
     };
-    // TODO: Is there any way to draw an outline on the ellipse? Like the default ellipse call in
-    // Processing does.
-    draw.ellipse()
-        .x_y(app.mouse.x, app.mouse.y)
-        .radius(80.0)
-        .color(color);
+    draw_ellipses(&draw, &kaleidoscope(app.mouse.position(), model.segments), radius, style);
 
     // Write the result of our drawing to the window's frame.
     draw.to_frame(app, &frame)
@@ -26,5 +355,7 @@ fn view(app: &App, frame: Frame) {
 }
 
 fn main() {
-    nannou::sketch(view);
+    nannou::app(model)
+        .update(update)
+        .run();
 }
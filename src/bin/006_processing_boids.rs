@@ -1,6 +1,7 @@
 // Based on the Processing Flocking example: https://processing.org/examples/flocking.html
 
 use nannou::prelude::*;
+use nannou_sketches::processing::Processing;
 
 const WIDTH: f32 = 800.0;
 const HEIGHT: f32 = 600.0;
@@ -9,9 +10,61 @@ const INITIAL_BOID_COUNT: u32 = 150;
 const BOID_RADIUS: f32 = 2.0;
 const MAX_SPEED: f32 = 2.0;
 const MAX_FORCE: f32 = 0.03;
+const SEPARATION_DIST: f32 = 25.0;
+const NEIGHBOR_DIST: f32 = 50.0;
+// How wide a cone boids see ahead of them by default, in degrees. Anything less than a full circle
+// leaves a blind spot behind them.
+const DEFAULT_FOV_DEGREES: f32 = 360.0;
+// How much each press of [ or ] changes a view angle, in degrees.
+const FOV_STEP_DEGREES: f32 = 15.0;
+
+// In soft walls mode, boids start turning back this far from the window border.
+const WALL_MARGIN: f32 = 50.0;
+const WALL_FORCE: f32 = 0.1;
+// How quickly the camera catches up to the flock in open mode. Higher is snappier.
+const CAMERA_FOLLOW: f32 = 0.05;
+// Spacing of the background grid in open mode, so there's something to see the camera move against.
+const GRID_SPACING: f32 = 100.0;
 
 const TWO_PI: f32 = 2.0 * std::f32::consts::PI;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BoundaryMode {
+    // Leave one side of the window and come back in on the other. Boids see each other across
+    // the seam.
+    Wrap,
+    // Reflect off the window border.
+    Bounce,
+    // Steer away from the window border when getting close.
+    SoftWalls,
+    // No border at all. The camera follows the middle of the flock instead.
+    Open,
+}
+
+impl BoundaryMode {
+    fn next(self) -> Self {
+        match self {
+            BoundaryMode::Wrap => BoundaryMode::Bounce,
+            BoundaryMode::Bounce => BoundaryMode::SoftWalls,
+            BoundaryMode::SoftWalls => BoundaryMode::Open,
+            BoundaryMode::Open => BoundaryMode::Wrap,
+        }
+    }
+
+    // The shortest vector from one position to another. When wrapping, that may cross the seam.
+    fn offset(self, from: Vec2, to: Vec2) -> Vec2 {
+        let offset = to - from;
+        if self != BoundaryMode::Wrap {
+            return offset;
+        }
+        // Boids wrap once they're fully past the border, so the world is a little bigger than the
+        // window.
+        let size = vec2(WIDTH, HEIGHT) + Vec2::splat(2.0 * BOID_RADIUS);
+        let wrap = |d: f32, size: f32| (d + size / 2.0).rem_euclid(size) - size / 2.0;
+        vec2(wrap(offset.x, size.x), wrap(offset.y, size.y))
+    }
+}
+
 struct Boid {
     pos: Vec2,
     vel: Vec2,
@@ -27,8 +80,97 @@ impl Boid {
     }
 }
 
+// Whether a boid offset by to_other from boid is inside boid's view cone, which is fov radians wide
+// and centered on its heading.
+fn can_see(boid: &Boid, to_other: Vec2, fov: f32) -> bool {
+    if fov >= TWO_PI {
+        return true;
+    }
+    if to_other == Vec2::ZERO || boid.vel == Vec2::ZERO {
+        return true;
+    }
+    boid.vel.angle_between(to_other).abs() <= fov / 2.0
+}
+
+// The flocking rules that look at neighbors. Each has its own view angle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NeighborRule {
+    Separation,
+    Alignment,
+    Cohesion,
+}
+
+impl NeighborRule {
+    fn radius(self) -> f32 {
+        match self {
+            NeighborRule::Separation => SEPARATION_DIST,
+            NeighborRule::Alignment | NeighborRule::Cohesion => NEIGHBOR_DIST,
+        }
+    }
+
+    fn debug_color(self) -> Rgba {
+        match self {
+            NeighborRule::Separation => Rgba::new(1.0, 0.4, 0.4, 1.0),
+            NeighborRule::Alignment => Rgba::new(0.4, 0.6, 1.0, 1.0),
+            NeighborRule::Cohesion => Rgba::new(1.0, 1.0, 0.4, 1.0),
+        }
+    }
+}
+
+const NEIGHBOR_RULES: [NeighborRule; 3] = [NeighborRule::Separation, NeighborRule::Alignment, NeighborRule::Cohesion];
+
 struct Model {
     boids: Vec<Boid>,
+    // View angles in radians.
+    separation_fov: f32,
+    alignment_fov: f32,
+    cohesion_fov: f32,
+    // Which view angle [ and ] change.
+    fov_rule: NeighborRule,
+    // The boid whose view cones and neighbors are drawn, if any.
+    debug_boid: Option<usize>,
+    boundary_mode: BoundaryMode,
+    // Where the middle of the window is in the world. Only moves in open mode.
+    camera: Vec2,
+}
+
+impl Model {
+    fn fov_mut(&mut self, rule: NeighborRule) -> &mut f32 {
+        match rule {
+            NeighborRule::Separation => &mut self.separation_fov,
+            NeighborRule::Alignment => &mut self.alignment_fov,
+            NeighborRule::Cohesion => &mut self.cohesion_fov,
+        }
+    }
+
+    fn fov(&self, rule: NeighborRule) -> f32 {
+        match rule {
+            NeighborRule::Separation => self.separation_fov,
+            NeighborRule::Alignment => self.alignment_fov,
+            NeighborRule::Cohesion => self.cohesion_fov,
+        }
+    }
+
+    fn adjust_fov(&mut self, degrees: f32) {
+        let rule = self.fov_rule;
+        let fov = self.fov_mut(rule);
+        *fov = clamp(*fov + degrees.to_radians(), 0.0, TWO_PI);
+        println!("{:?} view angle: {:.0} degrees", rule, fov.to_degrees());
+    }
+
+    // Indices of the boids that boid i takes into account for a rule.
+    fn visible_neighbors(&self, i: usize, rule: NeighborRule) -> Vec<usize> {
+        let boid = &self.boids[i];
+        self.boids.iter()
+            .enumerate()
+            .filter(|&(j, other)| {
+                let offset = self.boundary_mode.offset(boid.pos, other.pos);
+                let dist = offset.length();
+                j != i && dist > 0.0 && dist < rule.radius() && can_see(boid, offset, self.fov(rule))
+            })
+            .map(|(j, _)| j)
+            .collect()
+    }
 }
 
 fn model(app: &App) -> Model {
@@ -45,15 +187,58 @@ fn model(app: &App) -> Model {
 
     Model {
         boids: (0..INITIAL_BOID_COUNT).map(|_| Boid::new(0.0, 0.0)).collect(),
+        separation_fov: DEFAULT_FOV_DEGREES.to_radians(),
+        alignment_fov: DEFAULT_FOV_DEGREES.to_radians(),
+        cohesion_fov: DEFAULT_FOV_DEGREES.to_radians(),
+        fov_rule: NeighborRule::Separation,
+        debug_boid: None,
+        boundary_mode: BoundaryMode::Wrap,
+        camera: Vec2::ZERO,
     }
 }
 
 fn event(app: &App, model: &mut Model, event: WindowEvent) {
     match event {
         WindowEvent::MousePressed(MouseButton::Left) => {
-            let pos = app.mouse.position();
+            let pos = app.mouse.position() + model.camera;
             model.boids.push(Boid::new(pos.x, pos.y));
         }
+        WindowEvent::KeyPressed(Key::Key1) => {
+            model.fov_rule = NeighborRule::Separation;
+            println!("Adjusting view angle for: {:?}", model.fov_rule);
+        }
+        WindowEvent::KeyPressed(Key::Key2) => {
+            model.fov_rule = NeighborRule::Alignment;
+            println!("Adjusting view angle for: {:?}", model.fov_rule);
+        }
+        WindowEvent::KeyPressed(Key::Key3) => {
+            model.fov_rule = NeighborRule::Cohesion;
+            println!("Adjusting view angle for: {:?}", model.fov_rule);
+        }
+        WindowEvent::KeyPressed(Key::LBracket) => {
+            model.adjust_fov(-FOV_STEP_DEGREES);
+        }
+        WindowEvent::KeyPressed(Key::RBracket) => {
+            model.adjust_fov(FOV_STEP_DEGREES);
+        }
+        WindowEvent::KeyPressed(Key::B) => {
+            model.boundary_mode = model.boundary_mode.next();
+            if model.boundary_mode != BoundaryMode::Open {
+                model.camera = Vec2::ZERO;
+            }
+            println!("Boundary mode: {:?}", model.boundary_mode);
+        }
+        WindowEvent::KeyPressed(Key::V) => {
+            // Pick the boid closest to the mouse, or stop debugging.
+            let pos = app.mouse.position() + model.camera;
+            model.debug_boid = match model.debug_boid {
+                Some(_) => None,
+                None => model.boids.iter()
+                    .enumerate()
+                    .min_by(|(_, a), (_, b)| a.pos.distance(pos).partial_cmp(&b.pos.distance(pos)).unwrap())
+                    .map(|(i, _)| i),
+            };
+        }
         _ => {}
     }
 }
@@ -65,21 +250,15 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
         // Compute flocking accelerations.
         let sepration = {
             // Try to steer away from nearby boids.
-            let desired_separation = 25.0;
-
             let mut steer = Vec2::ZERO;
             let mut count = 0;
 
-            // Check if we're too close to all other boids.
-            for other in &model.boids {
-                let dist = boid.pos.distance(other.pos);
-
-                // If we're too close, modify our steering vector.
-                if dist > 0.0 && dist < desired_separation {
-                    let diff = (boid.pos - other.pos).normalize() / dist;
-                    steer += diff;
-                    count += 1;
-                }
+            // Modify our steering vector for every boid we're too close to.
+            for j in model.visible_neighbors(i, NeighborRule::Separation) {
+                let offset = model.boundary_mode.offset(boid.pos, model.boids[j].pos);
+                let diff = -offset.normalize() / offset.length();
+                steer += diff;
+                count += 1;
             }
 
             // Average out the steering.
@@ -97,17 +276,12 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
         };
         let alignment = {
             // Try to align with nearby boids.
-            let neighbor_dist = 50.0;
-
             let mut sum = Vec2::ZERO;
             let mut count = 0;
 
-            for other in &model.boids {
-                let dist = boid.pos.distance(other.pos);
-                if dist > 0.0 && dist < neighbor_dist {
-                    sum += other.vel;
-                    count += 1;
-                }
+            for j in model.visible_neighbors(i, NeighborRule::Alignment) {
+                sum += model.boids[j].vel;
+                count += 1;
             }
 
             if count > 0 {
@@ -120,17 +294,13 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
         };
         let cohesion = {
             // Try to move to the center of nearby boids.
-            let neighbor_dist = 50.0;
-
             let mut sum = Vec2::ZERO;
             let mut count = 0;
 
-            for other in &model.boids {
-                let dist = boid.pos.distance(other.pos);
-                if dist > 0.0 && dist < neighbor_dist {
-                    sum += other.pos;
-                    count += 1;
-                }
+            for j in model.visible_neighbors(i, NeighborRule::Cohesion) {
+                // Use the neighbor's position on our side of the seam.
+                sum += boid.pos + model.boundary_mode.offset(boid.pos, model.boids[j].pos);
+                count += 1;
             }
 
             if count > 0 {
@@ -142,50 +312,146 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
             }
         };
 
+        let walls = if model.boundary_mode == BoundaryMode::SoftWalls {
+            // Push back harder the closer we get to each wall.
+            let half = vec2(WIDTH, HEIGHT) / 2.0;
+            let push = |coord: f32, half: f32| {
+                let near_min = (WALL_MARGIN - (coord + half)).max(0.0) / WALL_MARGIN;
+                let near_max = (WALL_MARGIN - (half - coord)).max(0.0) / WALL_MARGIN;
+                near_min - near_max
+            };
+            vec2(push(boid.pos.x, half.x), push(boid.pos.y, half.y)) * WALL_FORCE
+        } else {
+            Vec2::ZERO
+        };
+
         // Update our physics.
         let boid = &mut model.boids[i];
-        let accel = sepration * 1.5 + alignment + cohesion;
+        let accel = sepration * 1.5 + alignment + cohesion + walls;
         boid.vel += accel;
         boid.vel = boid.vel.clamp_length_max(MAX_SPEED);
         boid.pos += boid.vel;
 
-        // Wrap around if we left the window border.
-        // Horizontal check.
-        if boid.pos.x < -(WIDTH / 2.0) - BOID_RADIUS {
-            boid.pos.x = (WIDTH / 2.0) + BOID_RADIUS
-        } else if boid.pos.x > (WIDTH / 2.0) + BOID_RADIUS {
-            boid.pos.x = -(WIDTH / 2.0) - BOID_RADIUS
-        }
-        // Vertical check.
-        if boid.pos.y < -(HEIGHT / 2.0) - BOID_RADIUS {
-            boid.pos.y = (HEIGHT / 2.0) + BOID_RADIUS
-        } else if boid.pos.y > (HEIGHT / 2.0) + BOID_RADIUS {
-            boid.pos.y = -(HEIGHT / 2.0) - BOID_RADIUS
+        match model.boundary_mode {
+            BoundaryMode::Wrap => {
+                // Wrap around if we left the window border.
+                // Horizontal check.
+                if boid.pos.x < -(WIDTH / 2.0) - BOID_RADIUS {
+                    boid.pos.x = (WIDTH / 2.0) + BOID_RADIUS
+                } else if boid.pos.x > (WIDTH / 2.0) + BOID_RADIUS {
+                    boid.pos.x = -(WIDTH / 2.0) - BOID_RADIUS
+                }
+                // Vertical check.
+                if boid.pos.y < -(HEIGHT / 2.0) - BOID_RADIUS {
+                    boid.pos.y = (HEIGHT / 2.0) + BOID_RADIUS
+                } else if boid.pos.y > (HEIGHT / 2.0) + BOID_RADIUS {
+                    boid.pos.y = -(HEIGHT / 2.0) - BOID_RADIUS
+                }
+            }
+            BoundaryMode::Bounce => {
+                // Flip our velocity if we hit the window border, unless we're already heading back.
+                let half = vec2(WIDTH, HEIGHT) / 2.0 - Vec2::splat(BOID_RADIUS);
+                if boid.pos.x.abs() > half.x && boid.pos.x * boid.vel.x > 0.0 {
+                    boid.vel.x = -boid.vel.x;
+                }
+                if boid.pos.y.abs() > half.y && boid.pos.y * boid.vel.y > 0.0 {
+                    boid.vel.y = -boid.vel.y;
+                }
+                boid.pos = boid.pos.clamp(-half, half);
+            }
+            BoundaryMode::SoftWalls => {
+                // The walls should turn us around before this, but don't let anyone escape.
+                let half = vec2(WIDTH, HEIGHT) / 2.0;
+                boid.pos = boid.pos.clamp(-half, half);
+            }
+            BoundaryMode::Open => {}
         }
     }
+
+    if model.boundary_mode == BoundaryMode::Open && !model.boids.is_empty() {
+        // Ease the camera toward the middle of the flock.
+        let centroid = model.boids.iter().fold(Vec2::ZERO, |acc, boid| acc + boid.pos) / model.boids.len() as f32;
+        model.camera += (centroid - model.camera) * CAMERA_FOLLOW;
+    }
 }
 
 fn view(app: &App, model: &Model, frame: Frame) {
-    let draw = app.draw();
+    // Draw the world through the camera.
+    let screen = app.draw();
+    let draw = screen.xy(-model.camera);
 
-    draw.background().color(Rgb::new(50u8, 50, 50));
+    let mut p = Processing::new(&draw);
+    p.background(50.0);
+
+    if model.boundary_mode == BoundaryMode::Open {
+        let rect = app.window_rect().shift(model.camera);
+        let grid_color = Rgba::new(1.0, 1.0, 1.0, 0.05);
+        let first = (vec2(rect.left(), rect.bottom()) / GRID_SPACING).floor() * GRID_SPACING;
+        let mut x = first.x;
+        while x < rect.right() {
+            draw.line().start(vec2(x, rect.bottom())).end(vec2(x, rect.top())).color(grid_color);
+            x += GRID_SPACING;
+        }
+        let mut y = first.y;
+        while y < rect.top() {
+            draw.line().start(vec2(rect.left(), y)).end(vec2(rect.right(), y)).color(grid_color);
+            y += GRID_SPACING;
+        }
+    }
 
     // Draw boids.
-    let (v1, v2, v3) = (
-        vec2(2.0 * BOID_RADIUS, 0.0),
-        vec2(-2.0 * BOID_RADIUS, -BOID_RADIUS),
-        vec2(-2.0 * BOID_RADIUS, BOID_RADIUS),
-    );
+    p.fill((200.0, 100.0));
+    p.stroke(255.0);
     for boid in &model.boids {
-        draw.tri()
-            .color(Rgba::new(200u8, 200, 200, 100))
-            .stroke(WHITE)
-            .points(v1, v2, v3)
-            .xy(boid.pos)
-            .rotate(boid.vel.angle());
+        p.push_matrix();
+        p.translate(boid.pos.x, boid.pos.y);
+        p.rotate(boid.vel.angle());
+        p.triangle(
+            2.0 * BOID_RADIUS, 0.0,
+            -2.0 * BOID_RADIUS, -BOID_RADIUS,
+            -2.0 * BOID_RADIUS, BOID_RADIUS,
+        );
+        p.pop_matrix();
+    }
+
+    // Show what the debug boid sees: a cone per rule and a line to each neighbor it counts.
+    if let Some(i) = model.debug_boid.filter(|&i| i < model.boids.len()) {
+        let boid = &model.boids[i];
+        let heading = boid.vel.angle();
+        for &rule in NEIGHBOR_RULES.iter().rev() {
+            let fov = model.fov(rule);
+            let color = rule.debug_color();
+            let segments = 32;
+            let arc = (0..=segments).map(|k| {
+                let angle = heading - fov / 2.0 + fov * k as f32 / segments as f32;
+                boid.pos + vec2(angle.cos(), angle.sin()) * rule.radius()
+            });
+            // Leave out the center for a full circle so there's no seam drawn through it.
+            let outline: Vec<Vec2> = if fov >= TWO_PI {
+                arc.collect()
+            } else {
+                std::iter::once(boid.pos).chain(arc).collect()
+            };
+            draw.polygon()
+                .color(Rgba::new(color.red, color.green, color.blue, 0.1))
+                .points(outline.iter().copied());
+            draw.polyline()
+                .weight(1.0)
+                .color(Rgba::new(color.red, color.green, color.blue, 0.5))
+                .points_closed(outline);
+
+            for j in model.visible_neighbors(i, rule) {
+                // Point across the seam at neighbors on the other side.
+                draw.line()
+                    .start(boid.pos)
+                    .end(boid.pos + model.boundary_mode.offset(boid.pos, model.boids[j].pos))
+                    .weight(1.0)
+                    .color(color);
+            }
+        }
     }
 
-    draw.to_frame(app, &frame)
+    screen.to_frame(app, &frame)
         .unwrap();
 }
 
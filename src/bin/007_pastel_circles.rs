@@ -2,12 +2,254 @@
 // Pastel circles that appear and shrink over time.
 // TODO: Try changing speed, transparency, delay in spawn?
 
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use nannou::image;
 use nannou::prelude::*;
 
 const WIDTH: f32 = 800.0;
 const HEIGHT: f32 = 600.0;
 const NUM_CIRCLES: u32 = 20;
 
+// How far circle hues can stray from their palette entry.
+const HUE_JITTER: f32 = 0.03;
+// Spacing between neighboring hues in the analogous palette.
+const ANALOGOUS_SPREAD: f32 = 1.0 / 12.0;
+// How many colors to pull out of an image.
+const IMAGE_SAMPLES: usize = 6;
+
+// Background hue drift, in turns per second.
+const BG_DRIFT_SPEED: f32 = 0.01;
+// The background hue skips over anything closer than this to a palette hue.
+const BG_MIN_HUE_DISTANCE: f32 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PaletteMode {
+    Random,
+    Analogous,
+    Complementary,
+    Triadic,
+    File,
+    Image,
+}
+
+impl PaletteMode {
+    fn next(self) -> Self {
+        match self {
+            PaletteMode::Random => PaletteMode::Analogous,
+            PaletteMode::Analogous => PaletteMode::Complementary,
+            PaletteMode::Complementary => PaletteMode::Triadic,
+            PaletteMode::Triadic => PaletteMode::File,
+            PaletteMode::File => PaletteMode::Image,
+            PaletteMode::Image => PaletteMode::Random,
+        }
+    }
+}
+
+// A color in HSV, with each component in [0, 1].
+#[derive(Clone, Copy, Debug)]
+struct PaletteColor {
+    hue: f32,
+    saturation: f32,
+    value: f32,
+}
+
+impl PaletteColor {
+    fn from_rgb8(r: u8, g: u8, b: u8) -> Self {
+        let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            ((g - b) / delta).rem_euclid(6.0) / 6.0
+        } else if max == g {
+            ((b - r) / delta + 2.0) / 6.0
+        } else {
+            ((r - g) / delta + 4.0) / 6.0
+        };
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+        Self {
+            hue,
+            saturation,
+            value: max,
+        }
+    }
+
+    // Parses "#rrggbb" or "rrggbb".
+    fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim().trim_start_matches('#');
+        if hex.len() != 6 {
+            return None;
+        }
+        let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+        let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+        let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
+        Some(Self::from_rgb8(r, g, b))
+    }
+}
+
+struct Palette {
+    // When empty, circles pick a uniformly random hue.
+    colors: Vec<PaletteColor>,
+    // Where the colors came from, if not generated.
+    source: Option<PathBuf>,
+}
+
+impl Palette {
+    fn generate(mode: PaletteMode, assets: Option<&Path>) -> Self {
+        let base_hue = random_f32();
+        let hues: Vec<f32> = match mode {
+            PaletteMode::Random => Vec::new(),
+            PaletteMode::Analogous => vec![
+                base_hue - ANALOGOUS_SPREAD,
+                base_hue,
+                base_hue + ANALOGOUS_SPREAD,
+            ],
+            PaletteMode::Complementary => vec![base_hue, base_hue + 0.5],
+            PaletteMode::Triadic => vec![base_hue, base_hue + 1.0 / 3.0, base_hue + 2.0 / 3.0],
+            PaletteMode::File => {
+                let path = assets.and_then(|assets| pick_file(&assets.join("palettes"), &["txt"]));
+                if let Some(palette) = path.and_then(|path| Palette::load_file(&path)) {
+                    return palette;
+                }
+                println!("No palette files found, falling back to random colors");
+                Vec::new()
+            }
+            PaletteMode::Image => {
+                let path = assets.and_then(|assets| pick_file(&assets.join("images"), &["png", "jpg", "jpeg"]));
+                if let Some(palette) = path.and_then(|path| Palette::sample_image(&path)) {
+                    return palette;
+                }
+                println!("No images found, falling back to random colors");
+                Vec::new()
+            }
+        };
+
+        // Generated hues use the same pastel saturation and value ranges as random circles.
+        let colors = hues.into_iter()
+            .map(|hue| PaletteColor {
+                hue: hue.rem_euclid(1.0),
+                saturation: random_range(0.2, 0.5),
+                value: random_range(0.7, 1.0),
+            })
+            .collect();
+
+        Self {
+            colors,
+            source: None,
+        }
+    }
+
+    // Reads one hex color per line. Blank lines are skipped.
+    fn load_file(path: &Path) -> Option<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                println!("Failed to read palette {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let colors: Vec<_> = contents.lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(PaletteColor::from_hex)
+            .collect();
+        if colors.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            colors,
+            source: Some(path.to_owned()),
+        })
+    }
+
+    // Samples colors from random pixels of an image.
+    fn sample_image(path: &Path) -> Option<Self> {
+        let image = match image::open(path) {
+            Ok(image) => image.to_rgb8(),
+            Err(e) => {
+                println!("Failed to open image {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let (w, h) = image.dimensions();
+        if w == 0 || h == 0 {
+            return None;
+        }
+        let colors = (0..IMAGE_SAMPLES)
+            .map(|_| {
+                let pixel = image.get_pixel(random_range(0, w), random_range(0, h));
+                PaletteColor::from_rgb8(pixel[0], pixel[1], pixel[2])
+            })
+            .collect();
+
+        Some(Self {
+            colors,
+            source: Some(path.to_owned()),
+        })
+    }
+
+    fn pick(&self) -> PaletteColor {
+        if self.colors.is_empty() {
+            return PaletteColor {
+                hue: random(),
+                saturation: random_range(0.2, 0.5),
+                value: random_range(0.7, 1.0),
+            };
+        }
+        let color = self.colors[random_range(0, self.colors.len())];
+        PaletteColor {
+            hue: (color.hue + random_range(-HUE_JITTER, HUE_JITTER)).rem_euclid(1.0),
+            ..color
+        }
+    }
+
+    // The first hue from hue upward that's at least BG_MIN_HUE_DISTANCE from every palette hue,
+    // or None if the palette leaves no room.
+    fn skip_close_hues(&self, hue: f32) -> Option<f32> {
+        let mut hue = hue;
+        // Each jump clears one palette hue, so going around once is enough.
+        for _ in 0..=self.colors.len() {
+            let close = self.colors.iter()
+                .map(|color| hue_offset(color.hue, hue))
+                .find(|offset| offset.abs() < BG_MIN_HUE_DISTANCE);
+            match close {
+                Some(offset) => hue = (hue - offset + BG_MIN_HUE_DISTANCE).rem_euclid(1.0),
+                None => return Some(hue),
+            }
+        }
+        None
+    }
+}
+
+// Shortest signed distance from one hue to another, in [-0.5, 0.5).
+fn hue_offset(from: f32, to: f32) -> f32 {
+    (to - from + 0.5).rem_euclid(1.0) - 0.5
+}
+
+// Picks a random file in dir with one of the given extensions.
+fn pick_file(dir: &Path, extensions: &[&str]) -> Option<PathBuf> {
+    let entries = fs::read_dir(dir).ok()?;
+    let files: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| extensions.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect();
+    if files.is_empty() {
+        return None;
+    }
+    Some(files[random_range(0, files.len())].clone())
+}
+
 struct PastelCircle {
     x: f32,
     y: f32,
@@ -19,17 +261,25 @@ struct PastelCircle {
 }
 
 impl PastelCircle {
-    fn new() -> Self {
+    fn new(palette: &Palette) -> Self {
+        let color = palette.pick();
         Self {
             x: random_range(-WIDTH / 2.0, WIDTH / 2.0),
             y: random_range(-HEIGHT / 2.0, HEIGHT / 2.0),
             radius: random_range(20.0, 80.0),
-            hue: random(),
-            saturation: random_range(0.2, 0.5),
-            value: random_range(0.7, 1.0),
+            hue: color.hue,
+            saturation: color.saturation,
+            value: color.value,
             alpha: random_range(0.5, 0.8),
         }
     }
+
+    fn recolor(&mut self, palette: &Palette) {
+        let color = palette.pick();
+        self.hue = color.hue;
+        self.saturation = color.saturation;
+        self.value = color.value;
+    }
 }
 
 struct Model {
@@ -38,9 +288,26 @@ struct Model {
     bg_value: f32,
     //spawn_timer: f32,
 
+    assets: Option<PathBuf>,
+    palette_mode: PaletteMode,
+    palette: Palette,
+
     circles: Vec<PastelCircle>,
 }
 
+impl Model {
+    fn reroll_palette(&mut self) {
+        self.palette = Palette::generate(self.palette_mode, self.assets.as_deref());
+        match &self.palette.source {
+            Some(source) => println!("Palette: {:?} ({})", self.palette_mode, source.display()),
+            None => println!("Palette: {:?}", self.palette_mode),
+        }
+        for circle in &mut self.circles {
+            circle.recolor(&self.palette);
+        }
+    }
+}
+
 fn model(app: &App) -> Model {
     let window_builder = nannou::winit::window::WindowBuilder::new()
         .with_resizable(false);
@@ -48,26 +315,54 @@ fn model(app: &App) -> Model {
         .window(window_builder)
         .size_pixels(WIDTH as u32, HEIGHT as u32)
         .title("Pastel Circles")
+        .event(event)
         .view(view)
         .build()
         .unwrap();
 
+    let assets = app.assets_path().ok();
+    let palette_mode = PaletteMode::Random;
+    let palette = Palette::generate(palette_mode, assets.as_deref());
+
     Model {
         bg_hue: random(),
         bg_saturation: 0.1,
         bg_value: 0.95,
         //spawn_timer: app.time + 1.0 / CIRCLES_SPAWNED_PER_SECOND,
 
-        circles: (0..NUM_CIRCLES).map(|_| PastelCircle::new()).collect(),
+        circles: (0..NUM_CIRCLES).map(|_| PastelCircle::new(&palette)).collect(),
+
+        assets,
+        palette_mode,
+        palette,
+    }
+}
+
+fn event(_app: &App, model: &mut Model, event: WindowEvent) {
+    match event {
+        WindowEvent::KeyPressed(Key::Space) => {
+            model.reroll_palette();
+        }
+        WindowEvent::KeyPressed(Key::P) => {
+            model.palette_mode = model.palette_mode.next();
+            model.reroll_palette();
+        }
+        _ => {}
     }
 }
 
-fn update(_app: &App, model: &mut Model, _update: Update) {
+fn update(_app: &App, model: &mut Model, update: Update) {
+    let dt = update.since_last.as_secs_f32();
+
+    // Slowly drift the background, but skip past the circles' hues so they stand out.
+    let hue = (model.bg_hue + BG_DRIFT_SPEED * dt).rem_euclid(1.0);
+    model.bg_hue = model.palette.skip_close_hues(hue).unwrap_or(hue);
+
     for circle in &mut model.circles {
         circle.radius -= 0.2;
         if circle.radius < 1.0 {
             // TODO: Delay respawning the circle some random amount of time?
-            *circle = PastelCircle::new();
+            *circle = PastelCircle::new(&model.palette);
         }
     }
 }
@@ -8,9 +8,17 @@
 // TODO: Want it to look less like a tree. More like one central trunk with small bits coming off.
 // Leaders with shorter paths are drawn dimmer and thinner.
 
-use std::time::Instant;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use nannou::image;
 use nannou::prelude::*;
+use nannou::wgpu;
+use nannou::window::Window;
+use nannou::Draw;
 
 const WIDTH: f32 = 800.0;
 const HEIGHT: f32 = 600.0;
@@ -27,8 +35,98 @@ const SHORT_PATH: usize = 10;
 const LONG_PATH: usize = 25;
 
 const LEADER_SPLIT_CHANCE: f32 = 0.02;
+// How far past the stroke's edge a sharp turn's corner can stick out, in stroke widths.
+const MITER_LIMIT: f32 = 2.0;
+
+const CLOUD_Y: f32 = HEIGHT / 2.0;
+const GROUND_Y: f32 = -HEIGHT / 2.0;
+// Where strikes start from until some are placed by clicking.
+const DEFAULT_ORIGIN_XS: [f32; 3] = [-200.0, 0.0, 200.0];
+// How hard leaders near a target turn toward it, compared to GROUND_BIAS_ANGLE.
+const TARGET_BIAS_ANGLE: f32 = 90.0;
+// A leader this close to a streamer's tip connects with it.
+const CONNECT_DIST: f32 = 8.0;
+const STREAMER_SPEED: f32 = 120.0;
+const STREAMER_SEGMENT: f32 = 8.0;
+const STREAMER_JITTER_DEGREES: f32 = 25.0;
+const STREAMER_WEIGHT: f32 = 1.0;
+const TARGET_CIRCLE_SEGMENTS: usize = 16;
+
+// How bright leaders and the return stroke are drawn. Above 1 only shows up through bloom.
+const LEADER_BRIGHTNESS: f32 = 1.5;
+const STRIKE_BRIGHTNESS: f32 = 4.0;
+const STRIKE_WEIGHT: f32 = 3.0;
+// The return stroke flares up this much more right when the strike lands, then settles.
+const RETURN_STROKE_BOOST: f32 = 8.0;
+const RETURN_STROKE_DECAY: f32 = 0.15;
+// The full screen flash after a strike, and how many seconds it takes to fade by about two thirds.
+const FLASH_INTENSITY: f32 = 0.6;
+const FLASH_DECAY: f32 = 0.08;
+
+const DEFAULT_BLOOM_STRENGTH: f32 = 1.5;
+const BLOOM_STRENGTH_STEP: f32 = 0.25;
+// Standard deviation of the blur, in window pixels.
+const DEFAULT_BLOOM_RADIUS: f32 = 8.0;
+const BLOOM_RADIUS_STEP: f32 = 2.0;
+const BLOOM_TINT: [f32; 3] = [0.55, 0.65, 1.0];
+const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// Relative to the assets folder.
+const CAPTURE_DIR: &str = "lightning";
+// How many updates --capture runs for at most before saving whatever it has.
+const HEADLESS_MAX_STEPS: usize = 100_000;
+// --capture shows the strike this long after it lands, once the flash has died down.
+const HEADLESS_SETTLE_SECONDS: f32 = 1.0;
+// Relative to CAPTURE_DIR.
+const THUNDER_DIR: &str = "thunder";
+
+// Storm mode. Seconds a strike stays up before the next one starts, and how long it takes to fade.
+const STORM_MIN_DELAY: f32 = 1.5;
+const STORM_MAX_DELAY: f32 = 6.0;
+const STRIKE_FADE_SECONDS: f32 = 1.5;
+// Average intra-cloud flashes per second.
+const CLOUD_FLASH_RATE: f32 = 0.3;
+const CLOUD_FLASH_SECONDS: f32 = 0.4;
+const CLOUD_FLASH_BRIGHTNESS: f32 = 2.0;
+const CLOUD_FLASH_WEIGHT: f32 = 1.5;
+const CLOUD_FLASH_INTENSITY: f32 = 0.2;
+const CLOUD_FLASH_SEGMENTS: usize = 12;
+const CLOUD_FLASH_MIN_SEGMENT: f32 = 15.0;
+const CLOUD_FLASH_MAX_SEGMENT: f32 = 40.0;
+// How far below the cloud line intra-cloud flashes crawl.
+const CLOUD_FLASH_DEPTH: f32 = 60.0;
+
+// Thunder. How far away from the viewer strikes are, in meters, and how big a pixel is.
+const MIN_STRIKE_DISTANCE: f32 = 300.0;
+const MAX_STRIKE_DISTANCE: f32 = 12_000.0;
+const METERS_PER_PIXEL: f32 = 5.0;
+const SPEED_OF_SOUND: f32 = 343.0;
+const THUNDER_SAMPLE_RATE: u32 = 44_100;
+// The rumble envelope is built in bins this many seconds long.
+const THUNDER_BIN_SECONDS: f32 = 0.01;
+// How long each bit of the channel keeps rumbling after its sound arrives.
+const THUNDER_RING_SECONDS: f32 = 0.4;
+// Seconds of decay after the sound from the farthest bit of the channel arrives.
+const THUNDER_TAIL_SECONDS: f32 = 3.0;
+// Close strikes crack, far ones only rumble since the air soaks up the highs.
+const THUNDER_MAX_CUTOFF: f32 = 4000.0;
+const THUNDER_MIN_CUTOFF: f32 = 80.0;
+const THUNDER_RUMBLE_CUTOFF: f32 = 60.0;
+
+// Dielectric breakdown model settings. The window is split into square cells this wide.
+const DBM_CELL: f32 = 8.0;
+// How strongly growth favors the strongest field. Near 0 grows blobs, higher grows straighter bolts.
+const DEFAULT_DBM_ETA: f32 = 3.0;
+const DBM_ETA_STEP: f32 = 0.5;
+// Relaxation sweeps when the field is first set up, and between each new channel cell.
+const DBM_INITIAL_SWEEPS: usize = 200;
+const DBM_SWEEPS_PER_GROWTH: usize = 4;
+const DBM_GROWTH_PER_UPDATE: usize = 4;
+// Over-relaxation factor. Between 1 and 2, higher converges faster.
+const DBM_SOR_OMEGA: f32 = 1.8;
 
 struct Leader {
+    // Not used yet, see the TODO in leader_stroke.
+    #[allow(dead_code)]
     spawn_time: Instant,
     // Where we've been. The last value is our current position.
     path: Vec<Vec2>,
@@ -38,12 +136,96 @@ struct Leader {
     dir: Vec2,
     // The distance to go before turning.
     turn_dist: f32,
-    // Our parent's index, if we have one.
-    parent: Option<usize>,
+    // Our parent's index and how many points of its path came before we branched off, if we have
+    // a parent.
+    parent: Option<(usize, usize)>,
+    // The tessellated part of our path that won't change anymore.
+    stroke: StrokeCache,
+}
+
+// A stroke tessellated up to some point along its path, two vertices per point with a quad between
+// each pair. Only the last point of a leader moves, so everything but its last two points can be
+// kept between frames, and finished paths are tessellated just once.
+#[derive(Default)]
+struct StrokeCache {
+    weight: f32,
+    vertices: Vec<Vec3>,
+    indices: Vec<usize>,
+}
+
+impl StrokeCache {
+    // The whole of a path that won't change anymore.
+    fn new(path: &[Vec2], weight: f32) -> Self {
+        let mut stroke = Self {
+            weight,
+            ..Self::default()
+        };
+        stroke.extend(path, path.len());
+        stroke
+    }
+
+    fn points(&self) -> usize {
+        self.vertices.len() / 2
+    }
+
+    // Tessellates path up to but not including the given point.
+    fn extend(&mut self, path: &[Vec2], end: usize) {
+        for i in self.points()..end {
+            let (left, right) = stroke_edges(path, i, self.weight);
+            self.vertices.push(left.extend(0.0));
+            self.vertices.push(right.extend(0.0));
+            if i > 0 {
+                self.indices.extend(quad_indices(i));
+            }
+        }
+    }
+
+    fn draw(&self, draw: &Draw, color: LinSrgba) {
+        draw.mesh()
+            .indexed(self.vertices.iter().copied(), self.indices.iter().copied())
+            .color(color);
+    }
+}
+
+// The quad joining path point i to the one before it.
+fn quad_indices(i: usize) -> [usize; 6] {
+    let v = (i - 1) * 2;
+    [v, v + 1, v + 2, v + 1, v + 3, v + 2]
+}
+
+// The left and right edges of a stroke of the given weight at path[i], mitered at turns and
+// squared off at the ends.
+fn stroke_edges(path: &[Vec2], i: usize, weight: f32) -> (Vec2, Vec2) {
+    let half = weight / 2.0;
+    let point = path[i];
+    let dir_in = i.checked_sub(1).map(|j| (point - path[j]).normalize_or_zero());
+    let dir_out = path.get(i + 1).map(|&next| (next - point).normalize_or_zero());
+    let (center, normal) = match (dir_in, dir_out) {
+        (Some(dir_in), Some(dir_out)) => {
+            let tangent = (dir_in + dir_out).normalize_or_zero();
+            let tangent = if tangent == Vec2::ZERO { dir_in } else { tangent };
+            // Stretch the corner so the stroke keeps its width through the turn.
+            let cos = tangent.dot(dir_in).max(1.0 / MITER_LIMIT);
+            (point, tangent.perp() * (half / cos))
+        }
+        (None, Some(dir)) => (point - dir * half, dir.perp() * half),
+        (Some(dir), None) => (point + dir * half, dir.perp() * half),
+        (None, None) => (point, Vec2::ZERO),
+    };
+    (center + normal, center - normal)
 }
 
 impl Leader {
-    fn new(pos: Vec2, rot_degrees: f32, parent: Option<usize>) -> Self {
+    // A leader that's already made it from one point to another, for generators that don't move
+    // leaders themselves.
+    fn from_segment(from: Vec2, to: Vec2, parent: Option<(usize, usize)>) -> Self {
+        Self {
+            path: vec![from, to],
+            ..Self::new(from, 0.0, parent)
+        }
+    }
+
+    fn new(pos: Vec2, rot_degrees: f32, parent: Option<(usize, usize)>) -> Self {
         let dir = Vec2::X.rotate(rot_degrees.to_radians());
 
         Self {
@@ -53,10 +235,13 @@ impl Leader {
             dir,
             turn_dist: random_range(MIN_TURN_DIST, MAX_TURN_DIST),
             parent,
+            stroke: StrokeCache::default(),
         }
     }
 
-    fn step(&mut self, dt: f32) {
+    // Moves the leader along, turning every so often. Turns lean toward attractor if there is one,
+    // otherwise toward the ground.
+    fn step(&mut self, dt: f32, attractor: Option<Vec2>) {
         let path_len = self.path.len();
 
         // Move in our current direction.
@@ -77,13 +262,17 @@ impl Leader {
                 if current_angle < 0.0 {
                     current_angle += 360.0;
                 }
-                let mut angle_diff_to_down = 270.0 - current_angle;
-                if angle_diff_to_down > 180.0 {
-                    angle_diff_to_down = -(360.0 - angle_diff_to_down);
+                let (target_angle, bias_angle) = match attractor {
+                    Some(target) => ((target - self.pos()).angle().to_degrees(), TARGET_BIAS_ANGLE),
+                    None => (270.0, GROUND_BIAS_ANGLE),
+                };
+                let mut angle_diff = (target_angle - current_angle).rem_euclid(360.0);
+                if angle_diff > 180.0 {
+                    angle_diff = -(360.0 - angle_diff);
                 }
-                //println!("Current: {} Diff to down: {}", current_angle, angle_diff_to_down);
-                // Bias the new angle down toward the ground.
-                let bias = angle_diff_to_down.signum() * (angle_diff_to_down / 180.0).powi(2) * GROUND_BIAS_ANGLE;
+                //println!("Current: {} Diff to target: {}", current_angle, angle_diff);
+                // Bias the new angle toward the target.
+                let bias = angle_diff.signum() * (angle_diff / 180.0).powi(2) * bias_angle;
                 current_angle + bias + random_range(MIN_TURN_DEGREES, MAX_TURN_DEGREES)
             };
             self.dir = Vec2::X.rotate(new_angle.to_radians());
@@ -93,28 +282,1190 @@ impl Leader {
     fn pos(&self) -> Vec2 {
         *self.path.last().unwrap()
     }
+
+    // Tessellates the newly settled part of our path. The whole stroke is redone when its weight
+    // changes, which stops happening once the path gets long.
+    fn tessellate(&mut self) {
+        let (weight, _) = leader_stroke(self);
+        if weight != self.stroke.weight {
+            self.stroke = StrokeCache {
+                weight,
+                ..StrokeCache::default()
+            };
+        }
+        self.stroke.extend(&self.path, self.path.len().saturating_sub(2));
+    }
+
+    // Draws the cached stroke along with the last couple of points, which are still moving.
+    fn draw(&self, draw: &Draw) {
+        let (_, alpha) = leader_stroke(self);
+        let settled = self.stroke.points();
+        let moving = (settled..self.path.len()).flat_map(|i| {
+            let (left, right) = stroke_edges(&self.path, i, self.stroke.weight);
+            [left.extend(0.0), right.extend(0.0)]
+        });
+        draw.mesh()
+            .indexed(
+                self.stroke.vertices.iter().copied().chain(moving),
+                self.stroke.indices.iter().copied().chain((settled.max(1)..self.path.len()).flat_map(quad_indices)),
+            )
+            .color(lin_srgba(LEADER_BRIGHTNESS, LEADER_BRIGHTNESS, LEADER_BRIGHTNESS, alpha));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TargetKind {
+    Tower,
+    Tree,
+    // Just a spot on the ground that sends up a streamer.
+    Streamer,
+}
+
+impl TargetKind {
+    fn next(self) -> Self {
+        match self {
+            TargetKind::Tower => TargetKind::Tree,
+            TargetKind::Tree => TargetKind::Streamer,
+            TargetKind::Streamer => TargetKind::Tower,
+        }
+    }
+
+    fn height(self) -> f32 {
+        match self {
+            TargetKind::Tower => 120.0,
+            TargetKind::Tree => 60.0,
+            TargetKind::Streamer => 0.0,
+        }
+    }
+
+    // Leaders closer than this to the top turn toward it, and it sends a streamer up to meet them.
+    // Taller things reach further.
+    fn attract_radius(self) -> f32 {
+        match self {
+            TargetKind::Tower => 220.0,
+            TargetKind::Tree => 150.0,
+            TargetKind::Streamer => 100.0,
+        }
+    }
+}
+
+// Something on the ground for lightning to hit.
+struct Target {
+    kind: TargetKind,
+    // Where it stands on the ground.
+    base: Vec2,
+    // The upward streamer, starting from the top, once a leader gets close enough.
+    streamer: Vec<Vec2>,
+}
+
+fn streamer_color() -> Rgba {
+    Rgba::new(0.6, 0.7, 1.0, 0.6)
+}
+
+impl Target {
+    fn new(x: f32, kind: TargetKind) -> Self {
+        Self {
+            kind,
+            base: vec2(x, GROUND_Y),
+            streamer: Vec::new(),
+        }
+    }
+
+    fn top(&self) -> Vec2 {
+        self.base + vec2(0.0, self.kind.height())
+    }
+
+    // Where the next leader to touch it connects: the end of the streamer, or the top if it
+    // hasn't sent one up yet.
+    fn tip(&self) -> Vec2 {
+        self.streamer.last().copied().unwrap_or_else(|| self.top())
+    }
+
+    // Whether a leader at pos has touched the object itself.
+    fn touches(&self, pos: Vec2) -> bool {
+        // A streamer is only a spot, but still covers a Laplacian cell so the channel can reach it.
+        let (half_width, height) = match self.kind {
+            TargetKind::Tower => (6.0, self.kind.height()),
+            TargetKind::Tree => (20.0, self.kind.height()),
+            TargetKind::Streamer => (DBM_CELL / 2.0, DBM_CELL),
+        };
+        pos.y <= self.base.y + height && (pos.x - self.base.x).abs() <= half_width
+    }
+
+    // Grows the streamer toward a leader at pos, wiggling a bit on the way up.
+    fn grow_streamer(&mut self, pos: Vec2, dt: f32) {
+        if self.streamer.is_empty() {
+            self.streamer = vec![self.top(), self.top()];
+        }
+        let len = self.streamer.len();
+        let from = self.streamer[len - 2];
+        let jitter = random_range(-STREAMER_JITTER_DEGREES, STREAMER_JITTER_DEGREES).to_radians();
+        let dir = (pos - self.streamer[len - 1]).normalize_or_zero().rotate(jitter);
+        self.streamer[len - 1] += dir * STREAMER_SPEED * dt;
+        if self.streamer[len - 1].distance(from) >= STREAMER_SEGMENT {
+            let tip = self.streamer[len - 1];
+            self.streamer.push(tip);
+        }
+    }
+
+    // The convex shapes we're drawn with, back to front. Shared with the CPU renderer so captures
+    // match the window.
+    fn shapes(&self) -> Vec<(Vec<Vec2>, Rgba)> {
+        let gray = Rgba::new(0.35, 0.35, 0.4, 1.0);
+        let rect = |center: Vec2, w: f32, h: f32| {
+            let half = vec2(w, h) / 2.0;
+            vec![center - half, center + vec2(half.x, -half.y), center + half, center + vec2(-half.x, half.y)]
+        };
+        match self.kind {
+            TargetKind::Tower => {
+                vec![(rect(self.base + vec2(0.0, self.kind.height() / 2.0), 8.0, self.kind.height()), gray)]
+            }
+            TargetKind::Tree => vec![
+                (rect(self.base + vec2(0.0, 10.0), 6.0, 20.0), Rgba::new(0.3, 0.2, 0.1, 1.0)),
+                (
+                    vec![self.base + vec2(-20.0, 15.0), self.base + vec2(20.0, 15.0), self.top()],
+                    Rgba::new(0.1, 0.3, 0.15, 1.0),
+                ),
+            ],
+            TargetKind::Streamer => {
+                let circle = (0..TARGET_CIRCLE_SEGMENTS)
+                    .map(|i| {
+                        let angle = i as f32 * TAU / TARGET_CIRCLE_SEGMENTS as f32;
+                        self.base + vec2(angle.cos(), angle.sin()) * 3.0
+                    })
+                    .collect();
+                vec![(circle, gray)]
+            }
+        }
+    }
+
+    fn draw(&self, draw: &Draw) {
+        for (points, color) in self.shapes() {
+            draw.polygon()
+                .points(points)
+                .color(color);
+        }
+        if self.streamer.len() > 1 {
+            draw.polyline()
+                .stroke_weight(STREAMER_WEIGHT)
+                .points(self.streamer.iter().cloned())
+                .color(streamer_color());
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Generator {
+    // Leaders wander down, turning and splitting at random.
+    RandomWalk,
+    // The channel grows one cell at a time where the electric field is strongest.
+    Laplacian,
+}
+
+impl Generator {
+    fn next(self) -> Self {
+        match self {
+            Generator::RandomWalk => Generator::Laplacian,
+            Generator::Laplacian => Generator::RandomWalk,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Cell {
+    Empty,
+    // Part of the channel, which is in the path of the given leader.
+    Channel(usize),
+    // The ground, or the given target standing on it.
+    Ground(Option<usize>),
+    // The cloud line, held at the channel's potential but never grown from.
+    Cloud,
+}
+
+// What happened when the channel tried to grow a cell.
+enum Growth {
+    Grew,
+    // The given leader reached the ground, or the given target on it.
+    Grounded(usize, Option<usize>),
+    // No cell next to the channel can grow.
+    Stuck,
+}
+
+// The dielectric breakdown model: the channel and cloud are held at potential 0 and the ground at 1,
+// the potential in between is solved for with the Laplace equation, and each new bit of channel is
+// picked next to the existing channel with probability proportional to the potential there raised
+// to the power eta. The channel is kept as leaders so it draws like the random walk does.
+struct Dbm {
+    cols: usize,
+    rows: usize,
+    // Row 0 is at the bottom.
+    cells: Vec<Cell>,
+    potential: Vec<f32>,
+}
+
+impl Dbm {
+    // Sets up the grid with the cloud along the top row and a channel cell under each origin, and
+    // returns the leaders starting there.
+    fn new(origins: &[Vec2], targets: &[Target]) -> (Self, Vec<Leader>) {
+        let cols = (WIDTH / DBM_CELL).ceil() as usize;
+        let rows = (HEIGHT / DBM_CELL).ceil() as usize;
+        let mut dbm = Self {
+            cols,
+            rows,
+            cells: vec![Cell::Empty; cols * rows],
+            // Start from a straight gradient, which is close to the answer anyway.
+            potential: (0..rows * cols)
+                .map(|i| 1.0 - (i / cols) as f32 / (rows - 1) as f32)
+                .collect(),
+        };
+
+        for col in 0..cols {
+            dbm.set(col, 0, Cell::Ground(None));
+        }
+        // Backwards, so earlier targets win where they overlap.
+        for (j, target) in targets.iter().enumerate().rev() {
+            dbm.add_target(j, target);
+        }
+
+        let row = rows - 1;
+        for col in 0..cols {
+            if dbm.cells[row * cols + col] == Cell::Empty {
+                dbm.set(col, row, Cell::Cloud);
+            }
+        }
+
+        let mut leaders = Vec::new();
+        for &origin in origins {
+            let col = ((origin.x + WIDTH / 2.0) / DBM_CELL).clamp(0.0, (cols - 1) as f32) as usize;
+            if dbm.cells[row * cols + col] == Cell::Cloud {
+                leaders.push(Leader::new(dbm.center(col, row), -90.0, None));
+                dbm.set(col, row, Cell::Channel(leaders.len() - 1));
+            }
+        }
+
+        dbm.relax(DBM_INITIAL_SWEEPS);
+        (dbm, leaders)
+    }
+
+    // Puts target j at ground potential. Cells the channel has already grown into stay channel.
+    fn add_target(&mut self, j: usize, target: &Target) {
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let i = row * self.cols + col;
+                if matches!(self.cells[i], Cell::Empty | Cell::Ground(_)) && target.touches(self.center(col, row)) {
+                    self.set(col, row, Cell::Ground(Some(j)));
+                }
+            }
+        }
+    }
+
+    fn center(&self, col: usize, row: usize) -> Vec2 {
+        vec2(
+            -WIDTH / 2.0 + (col as f32 + 0.5) * DBM_CELL,
+            GROUND_Y + (row as f32 + 0.5) * DBM_CELL,
+        )
+    }
+
+    // Sets a cell and pins its potential if it's a boundary.
+    fn set(&mut self, col: usize, row: usize, cell: Cell) {
+        let i = row * self.cols + col;
+        self.cells[i] = cell;
+        match cell {
+            Cell::Empty => {}
+            Cell::Channel(_) | Cell::Cloud => self.potential[i] = 0.0,
+            Cell::Ground(_) => self.potential[i] = 1.0,
+        }
+    }
+
+    // The cells around (col, row), including diagonals.
+    fn neighbors(&self, col: usize, row: usize) -> impl Iterator<Item = (usize, usize)> {
+        let (cols, rows) = (self.cols as i64, self.rows as i64);
+        let (col, row) = (col as i64, row as i64);
+        (-1..=1)
+            .flat_map(move |dy| (-1..=1).map(move |dx| (col + dx, row + dy)))
+            .filter(move |&(c, r)| (c, r) != (col, row) && c >= 0 && r >= 0 && c < cols && r < rows)
+            .map(|(c, r)| (c as usize, r as usize))
+    }
+
+    // Brings the potential closer to solving the Laplace equation with successive over-relaxation.
+    // Window edges don't let charge through, so they use the cell's own potential for the missing
+    // neighbor.
+    fn relax(&mut self, sweeps: usize) {
+        let (cols, rows) = (self.cols, self.rows);
+        for _ in 0..sweeps {
+            for row in 0..rows {
+                for col in 0..cols {
+                    let i = row * cols + col;
+                    if self.cells[i] != Cell::Empty {
+                        continue;
+                    }
+                    let p = &self.potential;
+                    let left = if col > 0 { p[i - 1] } else { p[i] };
+                    let right = if col + 1 < cols { p[i + 1] } else { p[i] };
+                    let down = if row > 0 { p[i - cols] } else { p[i] };
+                    let up = if row + 1 < rows { p[i + cols] } else { p[i] };
+                    let delta = DBM_SOR_OMEGA * ((left + right + down + up) / 4.0 - p[i]);
+                    self.potential[i] += delta;
+                }
+            }
+        }
+    }
+
+    // Adds one cell to the channel, extending the leader it grew from or branching off a new one.
+    fn grow(&mut self, leaders: &mut Vec<Leader>, eta: f32) -> Growth {
+        // Every empty cell touching the channel could be next.
+        let mut candidates = Vec::new();
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let i = row * self.cols + col;
+                if self.cells[i] != Cell::Empty {
+                    continue;
+                }
+                let touches_channel = self.neighbors(col, row)
+                    .any(|(c, r)| matches!(self.cells[r * self.cols + c], Cell::Channel(_)));
+                if touches_channel {
+                    candidates.push((col, row, self.potential[i].max(0.0)));
+                }
+            }
+        }
+        // Potentials are scaled so the largest is 1 before raising them to eta, otherwise a large
+        // eta underflows them all to 0.
+        let max_potential = candidates.iter().fold(0.0f32, |max, &(_, _, p)| max.max(p));
+        if max_potential <= 0.0 {
+            return Growth::Stuck;
+        }
+        for (_, _, weight) in candidates.iter_mut() {
+            *weight = (*weight / max_potential).powf(eta);
+        }
+        let total: f32 = candidates.iter().map(|&(_, _, weight)| weight).sum();
+
+        let mut pick = random_f32() * total;
+        let &(col, row, _) = candidates.iter()
+            .find(|&&(_, _, weight)| {
+                pick -= weight;
+                pick <= 0.0
+            })
+            .unwrap_or(candidates.last().unwrap());
+
+        // Grow from the closest bit of channel, preferring straight over diagonal.
+        let center = self.center(col, row);
+        let (parent_col, parent_row, parent_leader) = self.neighbors(col, row)
+            .filter_map(|(c, r)| match self.cells[r * self.cols + c] {
+                Cell::Channel(leader) => Some((c, r, leader)),
+                _ => None,
+            })
+            .min_by_key(|&(c, r, _)| (c != col) as u8 + (r != row) as u8)
+            .unwrap();
+        let parent_center = self.center(parent_col, parent_row);
+        let leader = if leaders[parent_leader].pos() == parent_center {
+            leaders[parent_leader].path.push(center);
+            parent_leader
+        } else {
+            let branch_point = leaders[parent_leader].path.iter()
+                .position(|&p| p == parent_center)
+                .map_or(0, |index| index + 1);
+            leaders.push(Leader::from_segment(parent_center, center, Some((parent_leader, branch_point))));
+            leaders.len() - 1
+        };
+        self.set(col, row, Cell::Channel(leader));
+
+        // Reaching anything at ground potential completes the strike.
+        let grounded = self.neighbors(col, row)
+            .find_map(|(c, r)| match self.cells[r * self.cols + c] {
+                Cell::Ground(target) => Some((c, r, target)),
+                _ => None,
+            });
+        match grounded {
+            Some((c, r, target)) => {
+                leaders[leader].path.push(self.center(c, r));
+                Growth::Grounded(leader, target)
+            }
+            None => Growth::Grew,
+        }
+    }
+}
+
+// Renders the scene into a float texture with additive blending so overlapping strokes add up past
+// white, blurs it at half size and adds the blur back on top.
+struct Bloom {
+    draw: Draw,
+    renderer: nannou::draw::Renderer,
+    // The scene is drawn multisampled like the window is, then resolved into scene_view for the
+    // blur. Without MSAA it's drawn into scene_view directly.
+    msaa_scene_view: Option<wgpu::TextureView>,
+    scene_view: wgpu::TextureView,
+    blur_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    // Horizontal then vertical.
+    blur_uniforms: [wgpu::Buffer; 2],
+    composite_uniforms: wgpu::Buffer,
+    // The first blurs the scene into the first half size texture, the second blurs that into the
+    // second.
+    blur_bind_groups: [wgpu::BindGroup; 2],
+    composite_bind_group: wgpu::BindGroup,
+    // Kept around for their views.
+    _scene_textures: (wgpu::Texture, Option<wgpu::Texture>),
+    _blur_textures: [wgpu::Texture; 2],
+    blur_views: [wgpu::TextureView; 2],
+    output_view: wgpu::TextureView,
+    // Copies the output to the window.
+    reshaper: wgpu::TextureReshaper,
+}
+
+impl Bloom {
+    fn new(window: &Window) -> Self {
+        let device = window.device();
+        let texture = |size: [u32; 2]| {
+            wgpu::TextureBuilder::new()
+                .size(size)
+                .usage(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
+                .format(HDR_FORMAT)
+                .build(device)
+        };
+        let full_size = [WIDTH as u32, HEIGHT as u32];
+        let half_size = [WIDTH as u32 / 2, HEIGHT as u32 / 2];
+        let scene = texture(full_size);
+        let blur_textures = [texture(half_size), texture(half_size)];
+        let output = texture(full_size);
+
+        let sample_count = window.msaa_samples();
+        let msaa_scene = if sample_count > 1 {
+            Some(wgpu::TextureBuilder::new()
+                .size(full_size)
+                .usage(wgpu::TextureUsages::RENDER_ATTACHMENT)
+                .sample_count(sample_count)
+                .format(HDR_FORMAT)
+                .build(device))
+        } else {
+            None
+        };
+        let renderer = nannou::draw::RendererBuilder::new()
+            .build_from_texture_descriptor(device, msaa_scene.as_ref().unwrap_or(&scene).descriptor());
+        let msaa_scene_view = msaa_scene.as_ref().map(|texture| texture.view().build());
+        let scene_view = scene.view().build();
+        let blur_views = [blur_textures[0].view().build(), blur_textures[1].view().build()];
+        let output_view = output.view().build();
+        let reshaper = wgpu::TextureReshaper::new(
+            device,
+            &output_view,
+            1,
+            output.sample_type(),
+            sample_count,
+            Frame::TEXTURE_FORMAT,
+        );
+
+        let module = device.create_shader_module(&wgpu::include_wgsl!("../shaders/lightning_bloom.wgsl"));
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty,
+            count: None,
+        };
+        let texture_entry = |binding| entry(binding, wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        });
+        let sampler_entry = |binding| entry(binding, wgpu::BindingType::Sampler {
+            filtering: true,
+            comparison: false,
+        });
+        let uniform_entry = |binding| entry(binding, wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        });
+        let blur_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lightning blur"),
+            entries: &[texture_entry(0), sampler_entry(1), uniform_entry(2)],
+        });
+        let composite_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lightning composite"),
+            entries: &[texture_entry(3), texture_entry(4), sampler_entry(5), uniform_entry(6)],
+        });
+        let blur_pipeline = create_fullscreen_pipeline(device, &module, &blur_layout, "fs_blur");
+        let composite_pipeline = create_fullscreen_pipeline(device, &module, &composite_layout, "fs_composite");
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("lightning bloom"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform_buffer = |size: usize| device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("lightning bloom uniforms"),
+            size: size as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let blur_uniforms = [uniform_buffer(blur_uniform_bytes(Vec2::ZERO, 1.0).len()), uniform_buffer(blur_uniform_bytes(Vec2::ZERO, 1.0).len())];
+        let composite_uniforms = uniform_buffer(composite_uniform_bytes([1.0; 3], 0.0, 0.0).len());
+
+        let blur_bind_group = |source: &wgpu::TextureView, uniforms: &wgpu::Buffer| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("lightning blur"),
+                layout: &blur_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: uniforms.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let blur_bind_groups = [
+            blur_bind_group(&scene_view, &blur_uniforms[0]),
+            blur_bind_group(&blur_views[0], &blur_uniforms[1]),
+        ];
+        let composite_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lightning composite"),
+            layout: &composite_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&scene_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&blur_views[1]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: composite_uniforms.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            draw: Draw::new(),
+            renderer,
+            msaa_scene_view,
+            scene_view,
+            blur_pipeline,
+            composite_pipeline,
+            blur_uniforms,
+            composite_uniforms,
+            blur_bind_groups,
+            composite_bind_group,
+            _scene_textures: (scene, msaa_scene),
+            _blur_textures: blur_textures,
+            blur_views,
+            output_view,
+            reshaper,
+        }
+    }
+
+    // Renders whatever is in self.draw, then blurs and composites it into the output.
+    fn render(&mut self, window: &Window, settings: &BloomSettings, flash: f32) {
+        let device = window.device();
+        let queue = window.queue();
+
+        // Blur in half size texels, so the radius is halved too.
+        let texel = vec2(2.0 / WIDTH, 2.0 / HEIGHT);
+        let sigma = (settings.radius / 2.0).max(0.5);
+        queue.write_buffer(&self.blur_uniforms[0], 0, &blur_uniform_bytes(vec2(texel.x, 0.0), sigma));
+        queue.write_buffer(&self.blur_uniforms[1], 0, &blur_uniform_bytes(vec2(0.0, texel.y), sigma));
+        let tint = if settings.tint { BLOOM_TINT } else { [1.0; 3] };
+        queue.write_buffer(&self.composite_uniforms, 0, &composite_uniform_bytes(tint, settings.strength, flash));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("lightning bloom"),
+        });
+        let (attachment, resolve_target) = match &self.msaa_scene_view {
+            Some(msaa_view) => (msaa_view, Some(&self.scene_view)),
+            None => (&self.scene_view, None),
+        };
+        let size = [WIDTH as u32, HEIGHT as u32];
+        self.renderer.encode_render_pass(device, &mut encoder, &self.draw, 1.0, size, attachment, resolve_target);
+        fullscreen_pass(&mut encoder, &self.blur_pipeline, &self.blur_bind_groups[0], &self.blur_views[0]);
+        fullscreen_pass(&mut encoder, &self.blur_pipeline, &self.blur_bind_groups[1], &self.blur_views[1]);
+        fullscreen_pass(&mut encoder, &self.composite_pipeline, &self.composite_bind_group, &self.output_view);
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+fn create_fullscreen_pipeline(
+    device: &wgpu::Device,
+    module: &wgpu::ShaderModule,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    fragment_entry_point: &str,
+) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(fragment_entry_point),
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(fragment_entry_point),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: fragment_entry_point,
+            targets: &[wgpu::ColorTargetState {
+                format: HDR_FORMAT,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+    })
+}
+
+// Draws a triangle over all of target with the given pipeline.
+fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    target: &wgpu::TextureView,
+) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("lightning bloom"),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: None,
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
+
+// Packs the `BlurUniforms` struct in lightning_bloom.wgsl.
+fn blur_uniform_bytes(direction: Vec2, sigma: f32) -> Vec<u8> {
+    let words = [
+        direction.x.to_ne_bytes(),
+        direction.y.to_ne_bytes(),
+        sigma.to_ne_bytes(),
+        0.0f32.to_ne_bytes(),
+    ];
+    words.concat()
+}
+
+// Packs the `CompositeUniforms` struct in lightning_bloom.wgsl.
+fn composite_uniform_bytes(tint: [f32; 3], strength: f32, flash: f32) -> Vec<u8> {
+    let words = [
+        tint[0].to_ne_bytes(),
+        tint[1].to_ne_bytes(),
+        tint[2].to_ne_bytes(),
+        1.0f32.to_ne_bytes(),
+        strength.to_ne_bytes(),
+        flash.to_ne_bytes(),
+        0.0f32.to_ne_bytes(),
+        0.0f32.to_ne_bytes(),
+    ];
+    words.concat()
+}
+
+struct BloomSettings {
+    // Off draws straight to the window, like before there was bloom.
+    enabled: bool,
+    strength: f32,
+    // Standard deviation of the blur in window pixels.
+    radius: f32,
+    // Give the glow a cold blue tint instead of leaving it white.
+    tint: bool,
+}
+
+// Renders a frame on the CPU with the same bloom as the GPU path and saves it. This doesn't need a
+// window, so it's also what `--capture` uses.
+fn capture(model: &Model, path: &Path) -> Result<(), String> {
+    let (w, h) = (WIDTH as usize, HEIGHT as usize);
+    let mut scene = vec![[0.0f32; 3]; w * h];
+
+    // Targets are drawn normally, like in draw_scene.
+    for target in &model.targets {
+        for (points, color) in target.shapes() {
+            let color = color.into_linear();
+            fill_convex(w, h, &points, |i, coverage| blend_over(&mut scene[i], color, coverage));
+        }
+        let color = streamer_color().into_linear();
+        stroke_polyline(w, h, &target.streamer, STREAMER_WEIGHT, |i, coverage| blend_over(&mut scene[i], color, coverage));
+    }
+
+    // Lightning adds up, with each polyline keeping the brightest of its overlapping segments.
+    let mut add_polyline = |points: &[Vec2], weight: f32, brightness: f32| {
+        let mut layer = vec![0.0f32; w * h];
+        stroke_polyline(w, h, points, weight, |i, coverage| layer[i] = layer[i].max(coverage * brightness));
+        for (pixel, value) in scene.iter_mut().zip(layer) {
+            for channel in pixel.iter_mut() {
+                *channel += value;
+            }
+        }
+    };
+    for leader in &model.leaders {
+        let (weight, alpha) = leader_stroke(leader);
+        add_polyline(&leader.path, weight, alpha * LEADER_BRIGHTNESS);
+    }
+    if let Some(strike) = &model.strike {
+        add_polyline(&strike.path, STRIKE_WEIGHT, model.strike_brightness());
+    }
+    for (strike, at) in &model.storm.fading {
+        add_polyline(&strike.path, STRIKE_WEIGHT, STRIKE_BRIGHTNESS * Storm::fade(at.elapsed()));
+    }
+    for flash in &model.storm.cloud_flashes {
+        add_polyline(&flash.path, CLOUD_FLASH_WEIGHT, CLOUD_FLASH_BRIGHTNESS * flash.life());
+    }
+
+    // Separable gaussian, clamping at the edges like the GPU sampler does.
+    let sigma = model.bloom_settings.radius.max(0.5);
+    let radius = (sigma * 3.0).ceil() as i64;
+    let kernel: Vec<f32> = (-radius..=radius).map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp()).collect();
+    let total: f32 = kernel.iter().sum();
+    let blur_pass = |src: &[[f32; 3]], step: (i64, i64)| -> Vec<[f32; 3]> {
+        let mut dst = vec![[0.0; 3]; w * h];
+        for y in 0..h as i64 {
+            for x in 0..w as i64 {
+                let mut sum = [0.0; 3];
+                for (k, weight) in kernel.iter().enumerate() {
+                    let offset = k as i64 - radius;
+                    let sx = (x + offset * step.0).clamp(0, w as i64 - 1);
+                    let sy = (y + offset * step.1).clamp(0, h as i64 - 1);
+                    let pixel = src[(sy * w as i64 + sx) as usize];
+                    for c in 0..3 {
+                        sum[c] += pixel[c] * weight;
+                    }
+                }
+                dst[(y * w as i64 + x) as usize] = sum.map(|channel| channel / total);
+            }
+        }
+        dst
+    };
+    let bloom = blur_pass(&blur_pass(&scene, (1, 0)), (0, 1));
+
+    let settings = &model.bloom_settings;
+    let tint = if settings.tint { BLOOM_TINT } else { [1.0; 3] };
+    let flash = model.flash();
+    let image = image::RgbImage::from_fn(w as u32, h as u32, |x, y| {
+        let i = y as usize * w + x as usize;
+        let channel = |c: usize| {
+            let linear = 1.0 - (-(scene[i][c] + bloom[i][c] * settings.strength * tint[c] + flash)).exp();
+            // Linear to sRGB.
+            let srgb = if linear <= 0.003_130_8 { linear * 12.92 } else { 1.055 * linear.powf(1.0 / 2.4) - 0.055 };
+            (srgb.clamp(0.0, 1.0) * 255.0).round() as u8
+        };
+        image::Rgb([channel(0), channel(1), channel(2)])
+    });
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    image.save(path).map_err(|e| e.to_string())
+}
+
+// Window coordinates, with y up, to image pixel coordinates.
+fn to_pixel(p: Vec2) -> Vec2 {
+    vec2(p.x + WIDTH / 2.0, HEIGHT / 2.0 - p.y)
+}
+
+// Calls plot with each pixel index an antialiased polyline covers and how much it covers it.
+// Pixels where segments meet get plotted once per segment.
+fn stroke_polyline(w: usize, h: usize, points: &[Vec2], weight: f32, mut plot: impl FnMut(usize, f32)) {
+    let half = weight.max(1.0) / 2.0;
+    for (&a, &b) in points.iter().zip(points.iter().skip(1)) {
+        let (a, b) = (to_pixel(a), to_pixel(b));
+        let min = (a.min(b) - Vec2::splat(half + 1.0)).max(Vec2::ZERO);
+        let max = (a.max(b) + Vec2::splat(half + 1.0)).min(vec2(w as f32 - 1.0, h as f32 - 1.0));
+        if min.x > max.x || min.y > max.y {
+            continue;
+        }
+        for y in min.y as usize..=max.y as usize {
+            for x in min.x as usize..=max.x as usize {
+                let p = vec2(x as f32 + 0.5, y as f32 + 0.5);
+                let ab = b - a;
+                let t = if ab == Vec2::ZERO { 0.0 } else { ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0) };
+                let dist = p.distance(a + ab * t);
+                let coverage = (half + 0.5 - dist).clamp(0.0, 1.0);
+                if coverage > 0.0 {
+                    plot(y * w + x, coverage);
+                }
+            }
+        }
+    }
+}
+
+// Calls plot with each pixel index whose center is inside a convex polygon, wound either way.
+fn fill_convex(w: usize, h: usize, points: &[Vec2], mut plot: impl FnMut(usize, f32)) {
+    let points: Vec<Vec2> = points.iter().map(|&p| to_pixel(p)).collect();
+    let min = points.iter().fold(Vec2::splat(f32::MAX), |min, &p| min.min(p)).max(Vec2::ZERO);
+    let max = points.iter().fold(Vec2::splat(f32::MIN), |max, &p| max.max(p)).min(vec2(w as f32 - 1.0, h as f32 - 1.0));
+    if min.x > max.x || min.y > max.y {
+        return;
+    }
+    for y in min.y as usize..=max.y as usize {
+        for x in min.x as usize..=max.x as usize {
+            let p = vec2(x as f32 + 0.5, y as f32 + 0.5);
+            let sides = points.iter().zip(points.iter().cycle().skip(1)).map(|(&a, &b)| (b - a).perp_dot(p - a));
+            let (mut left, mut right) = (false, false);
+            for side in sides {
+                left |= side < 0.0;
+                right |= side > 0.0;
+            }
+            if !(left && right) {
+                plot(y * w + x, 1.0);
+            }
+        }
+    }
+}
+
+fn blend_over(pixel: &mut [f32; 3], color: LinSrgba, coverage: f32) {
+    let alpha = color.alpha * coverage;
+    let rgb = [color.red, color.green, color.blue];
+    for (channel, value) in pixel.iter_mut().zip(rgb) {
+        *channel += (value - *channel) * alpha;
+    }
+}
+
+// Stroke weight and alpha for a leader. Longer paths are drawn thicker and brighter.
+fn leader_stroke(leader: &Leader) -> (f32, f32) {
+    // TODO: Determine a measure based on spawn time (and path length? maybe total length
+    // traveled?)
+    let len = clamp(leader.path.len(), SHORT_PATH, LONG_PATH);
+    let stroke_weight = map_range(len, SHORT_PATH, LONG_PATH, 0.2, 3.0);
+    let alpha = map_range(len, SHORT_PATH, LONG_PATH, 0.3, 1.0);
+    (stroke_weight, alpha)
+}
+
+// A short crawler that lights up inside the cloud without reaching the ground.
+struct CloudFlash {
+    path: Vec<Vec2>,
+    stroke: StrokeCache,
+    at: Instant,
+}
+
+impl CloudFlash {
+    fn new() -> Self {
+        let mut pos = vec2(random_range(-WIDTH / 2.0, WIDTH / 2.0), CLOUD_Y - random_range(10.0, CLOUD_FLASH_DEPTH));
+        let mut angle = if random_f32() < 0.5 { 0.0 } else { PI };
+        let mut path = vec![pos];
+        for _ in 0..CLOUD_FLASH_SEGMENTS {
+            angle += random_range(MIN_TURN_DEGREES, MAX_TURN_DEGREES).to_radians();
+            pos += vec2(angle.cos(), angle.sin()) * random_range(CLOUD_FLASH_MIN_SEGMENT, CLOUD_FLASH_MAX_SEGMENT);
+            // Stay inside the cloud.
+            pos.y = pos.y.clamp(CLOUD_Y - CLOUD_FLASH_DEPTH, CLOUD_Y);
+            path.push(pos);
+        }
+        Self {
+            stroke: StrokeCache::new(&path, CLOUD_FLASH_WEIGHT),
+            path,
+            at: Instant::now(),
+        }
+    }
+
+    // Goes from 1 down to 0 over its lifetime.
+    fn life(&self) -> f32 {
+        1.0 - (self.at.elapsed().as_secs_f32() / CLOUD_FLASH_SECONDS).min(1.0)
+    }
+}
+
+// A strike that made it to the ground, traced from the cloud down once it lands.
+struct Strike {
+    path: Vec<Vec2>,
+    stroke: StrokeCache,
+}
+
+impl Strike {
+    fn new(path: Vec<Vec2>) -> Self {
+        Self {
+            stroke: StrokeCache::new(&path, STRIKE_WEIGHT),
+            path,
+        }
+    }
+}
+
+struct Storm {
+    // Off stops after each strike until R is pressed.
+    enabled: bool,
+    // When to clear the current strike and start the next one.
+    next_strike: Option<Instant>,
+    // Earlier strikes fading out, with when they started fading.
+    fading: Vec<(Strike, Instant)>,
+    cloud_flashes: Vec<CloudFlash>,
+    // Whether to write a WAV for each strike. They're a few megabytes each.
+    write_thunder: bool,
+    // How much thunder we've written, for naming the files.
+    thunder_count: usize,
+}
+
+impl Storm {
+    fn fade(age: Duration) -> f32 {
+        1.0 - (age.as_secs_f32() / STRIKE_FADE_SECONDS).min(1.0)
+    }
+}
+
+// How many seconds sound from a point on screen takes to reach a viewer standing distance meters
+// in front of where the channel meets the ground.
+fn arrival_time(point: Vec2, distance: f32) -> f32 {
+    let across = point.x * METERS_PER_PIXEL;
+    let up = (point.y - GROUND_Y) * METERS_PER_PIXEL;
+    (across * across + up * up + distance * distance).sqrt() / SPEED_OF_SOUND
+}
+
+// Synthesizes thunder for a channel. Each segment adds a burst of noise when its sound arrives,
+// louder for longer and closer segments, so the rumble follows the channel's shape. The start is
+// silent until the sound from the nearest point arrives.
+fn synthesize_thunder(path: &[Vec2], distance: f32) -> Vec<i16> {
+    let arrivals: Vec<(f32, f32)> = path.iter()
+        .zip(path.iter().skip(1))
+        .map(|(&a, &b)| {
+            let t = arrival_time((a + b) / 2.0, distance);
+            let energy = a.distance(b) * METERS_PER_PIXEL / (t * SPEED_OF_SOUND);
+            (t, energy)
+        })
+        .collect();
+    let end = arrivals.iter().map(|&(t, _)| t).fold(0.0, f32::max) + THUNDER_TAIL_SECONDS;
+
+    let mut bins = vec![0.0f32; (end / THUNDER_BIN_SECONDS).ceil() as usize + 1];
+    for &(t, energy) in &arrivals {
+        bins[(t / THUNDER_BIN_SECONDS) as usize] += energy;
+    }
+    // Let each burst ring out instead of stopping dead at the end of its bin.
+    let ring = (-THUNDER_BIN_SECONDS / THUNDER_RING_SECONDS).exp();
+    let mut level = 0.0;
+    for bin in &mut bins {
+        level = level * ring + *bin;
+        *bin = level;
+    }
+
+    let rate = THUNDER_SAMPLE_RATE as f32;
+    let one_pole = |cutoff: f32| 1.0 - (-2.0 * PI * cutoff / rate).exp();
+    let cutoff = (THUNDER_MAX_CUTOFF * MIN_STRIKE_DISTANCE / distance).clamp(THUNDER_MIN_CUTOFF, THUNDER_MAX_CUTOFF);
+    let (crack_alpha, rumble_alpha) = (one_pole(cutoff), one_pole(THUNDER_RUMBLE_CUTOFF));
+    // Two poles each, so the noise doesn't hiss through.
+    let mut crack = [0.0f32; 2];
+    let mut rumble = [0.0f32; 2];
+    let samples: Vec<f32> = (0..(end * rate) as usize)
+        .map(|i| {
+            let noise = random_f32() * 2.0 - 1.0;
+            crack[0] += crack_alpha * (noise - crack[0]);
+            crack[1] += crack_alpha * (crack[0] - crack[1]);
+            rumble[0] += rumble_alpha * (noise - rumble[0]);
+            rumble[1] += rumble_alpha * (rumble[0] - rumble[1]);
+            let envelope = bins[((i as f32 / rate) / THUNDER_BIN_SECONDS) as usize];
+            envelope * (crack[1] + rumble[1] * 4.0)
+        })
+        .collect();
+
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    let scale = if peak > 0.0 { 0.9 * i16::MAX as f32 / peak } else { 0.0 };
+    samples.iter().map(|s| (s * scale) as i16).collect()
+}
+
+// Writes 16 bit mono PCM.
+fn write_wav(path: &Path, samples: &[i16]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    let data_len = samples.len() as u32 * 2;
+    file.write_all(b"RIFF")?;
+    file.write_all(&(36 + data_len).to_le_bytes())?;
+    file.write_all(b"WAVEfmt ")?;
+    file.write_all(&16u32.to_le_bytes())?;
+    // PCM, one channel.
+    file.write_all(&1u16.to_le_bytes())?;
+    file.write_all(&1u16.to_le_bytes())?;
+    file.write_all(&THUNDER_SAMPLE_RATE.to_le_bytes())?;
+    file.write_all(&(THUNDER_SAMPLE_RATE * 2).to_le_bytes())?;
+    file.write_all(&2u16.to_le_bytes())?;
+    file.write_all(&16u16.to_le_bytes())?;
+    file.write_all(b"data")?;
+    file.write_all(&data_len.to_le_bytes())?;
+    for sample in samples {
+        file.write_all(&sample.to_le_bytes())?;
+    }
+    file.flush()
+}
+
+// Picks how far away a strike was and, if enabled, writes its thunder in the background.
+fn emit_thunder(app: &App, storm: &mut Storm, path: Vec<Vec2>) {
+    let distance = random_range(MIN_STRIKE_DISTANCE, MAX_STRIKE_DISTANCE);
+    let delay = path.iter().map(|&p| arrival_time(p, distance)).fold(f32::MAX, f32::min);
+    println!("Strike {:.1} km away, thunder in {:.1} s", distance / 1000.0, delay);
+    if !storm.write_thunder {
+        return;
+    }
+
+    let dir = match app.assets_path() {
+        Ok(assets) => assets.join(CAPTURE_DIR).join(THUNDER_DIR),
+        Err(e) => {
+            println!("No assets folder to save thunder to: {}", e);
+            return;
+        }
+    };
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let path_name = dir.join(format!("thunder-{}-{}.wav", secs, storm.thunder_count));
+    storm.thunder_count += 1;
+    thread::spawn(move || {
+        let samples = synthesize_thunder(&path, distance);
+        let result = std::fs::create_dir_all(&dir).and_then(|_| write_wav(&path_name, &samples));
+        if let Err(e) = result {
+            println!("Failed to save {}: {}", path_name.display(), e);
+        }
+    });
 }
 
 struct Model {
     simulate: bool,
+    generator: Generator,
+    // Only used by the Laplacian generator.
+    dbm: Option<Dbm>,
+    eta: f32,
+
+    // Where leaders start, along the cloud line.
+    origins: Vec<Vec2>,
+    targets: Vec<Target>,
+    // What right clicking places.
+    target_kind: TargetKind,
 
     leaders: Vec<Leader>,
     grounded_leader: Option<usize>,
+    // The target the strike connected to, if it didn't just hit open ground.
+    struck_target: Option<usize>,
+    // When the strike connected, for timing the return stroke and flash.
+    grounded_at: Option<Instant>,
+    strike: Option<Strike>,
+
+    // None while bloom is off, so the textures are only made when it's used.
+    bloom: Option<Bloom>,
+    bloom_settings: BloomSettings,
+
+    storm: Storm,
 }
 
 impl Model {
     fn reset(&mut self) {
-        self.leaders = vec![
-            Leader::new(vec2(0.0, HEIGHT / 2.0), -90.0, None),
-        ];
+        match self.generator {
+            Generator::RandomWalk => {
+                self.leaders = self.origins.iter()
+                    .map(|&origin| Leader::new(origin, -90.0, None))
+                    .collect();
+                self.dbm = None;
+            }
+            Generator::Laplacian => {
+                let (dbm, leaders) = Dbm::new(&self.origins, &self.targets);
+                self.leaders = leaders;
+                self.dbm = Some(dbm);
+            }
+        }
         self.grounded_leader = None;
+        self.struck_target = None;
+        self.grounded_at = None;
+        self.strike = None;
+        self.storm.next_strike = None;
+        for target in &mut self.targets {
+            target.streamer.clear();
+        }
+    }
+
+    // Keeps the current strike around to fade out, then starts the next one.
+    fn next_strike(&mut self) {
+        if let Some(strike) = self.strike.take() {
+            self.storm.fading.push((strike, Instant::now()));
+        }
+        self.reset();
+    }
+
+    // Marks the strike as having landed at the given time, tracing its path once so it doesn't
+    // have to be followed back up the branches every frame.
+    fn land(&mut self, at: Option<Instant>) {
+        self.grounded_at = at;
+        self.strike = Some(Strike::new(self.strike_path()));
+    }
+
+    // The path from the cloud down to where the strike landed, following each branch back to where
+    // it split off its parent.
+    fn strike_path(&self) -> Vec<Vec2> {
+        let mut sections = Vec::new();
+        let mut next = self.grounded_leader.map(|i| (i, self.leaders[i].path.len()));
+        while let Some((i, len)) = next {
+            let leader = &self.leaders[i];
+            sections.push(&leader.path[..len.min(leader.path.len())]);
+            next = leader.parent;
+        }
+        sections.into_iter().rev().flatten().copied().collect()
+    }
+
+    fn seconds_since_strike(&self) -> Option<f32> {
+        self.grounded_at.map(|at| at.elapsed().as_secs_f32())
+    }
+
+    // Flares up right after the strike lands, then settles to a steady glow.
+    fn strike_brightness(&self) -> f32 {
+        let t = self.seconds_since_strike().unwrap_or(0.0);
+        STRIKE_BRIGHTNESS + RETURN_STROKE_BOOST * (-t / RETURN_STROKE_DECAY).exp()
+    }
+
+    fn flash(&self) -> f32 {
+        let strike = match self.seconds_since_strike() {
+            Some(t) => FLASH_INTENSITY * (-t / FLASH_DECAY).exp(),
+            None => 0.0,
+        };
+        let cloud: f32 = self.storm.cloud_flashes.iter().map(|flash| CLOUD_FLASH_INTENSITY * flash.life()).sum();
+        strike + cloud
+    }
+}
+
+// Draws targets, leaders and the strike. Strokes add up, so in HDR their brightness can go past
+// white where they overlap.
+fn draw_scene(draw: &Draw, model: &Model) {
+    draw.background()
+        //.color(Rgb::new(230u8, 230, 250));
+        .color(BLACK);
+
+    for target in &model.targets {
+        target.draw(draw);
+    }
+
+    let draw = draw.color_blend(BLEND_ADD);
+
+    for (strike, at) in &model.storm.fading {
+        let brightness = STRIKE_BRIGHTNESS * Storm::fade(at.elapsed());
+        strike.stroke.draw(&draw, lin_srgba(brightness, brightness, brightness, 1.0));
+    }
+    for flash in &model.storm.cloud_flashes {
+        let brightness = CLOUD_FLASH_BRIGHTNESS * flash.life();
+        flash.stroke.draw(&draw, lin_srgba(brightness, brightness, brightness, 1.0));
+    }
+
+    for leader in &model.leaders {
+        leader.draw(&draw);
+    }
+
+    // The return stroke lights up the whole channel from cloud to ground.
+    if let Some(strike) = &model.strike {
+        let brightness = model.strike_brightness();
+        strike.stroke.draw(&draw, lin_srgba(brightness, brightness, brightness, 1.0));
     }
 }
 
+fn default_origins() -> Vec<Vec2> {
+    DEFAULT_ORIGIN_XS.iter().map(|&x| vec2(x, CLOUD_Y)).collect()
+}
+
 fn model(app: &App) -> Model {
     let window_builder = nannou::winit::window::WindowBuilder::new()
         .with_resizable(false);
-    let _window = app.new_window()
+    let window_id = app.new_window()
         .window(window_builder)
         .size_pixels(WIDTH as u32, HEIGHT as u32)
         .title("Lightning")
@@ -123,86 +1474,329 @@ fn model(app: &App) -> Model {
         .build()
         .unwrap();
 
-    Model {
-        simulate: true,
+    let mut model = Model::new();
+    model.bloom = Some(Bloom::new(&app.window(window_id).unwrap()));
+    model
+}
+
+impl Model {
+    // Everything but the window's bloom textures, so strikes can also be grown headless.
+    fn new() -> Self {
+        let mut model = Model {
+            simulate: true,
+            generator: Generator::RandomWalk,
+            dbm: None,
+            eta: DEFAULT_DBM_ETA,
+
+            origins: default_origins(),
+            targets: Vec::new(),
+            target_kind: TargetKind::Tower,
 
-        leaders: vec![
-            Leader::new(vec2(0.0, HEIGHT / 2.0), -90.0, None),
-        ],
-        grounded_leader: None,
+            leaders: Vec::new(),
+            grounded_leader: None,
+            struck_target: None,
+            grounded_at: None,
+            strike: None,
+
+            bloom: None,
+            bloom_settings: BloomSettings {
+                enabled: true,
+                strength: DEFAULT_BLOOM_STRENGTH,
+                radius: DEFAULT_BLOOM_RADIUS,
+                tint: true,
+            },
+
+            storm: Storm {
+                enabled: false,
+                next_strike: None,
+                fading: Vec::new(),
+                cloud_flashes: Vec::new(),
+                write_thunder: false,
+                thunder_count: 0,
+            },
+        };
+        model.reset();
+        model
     }
 }
 
-fn event(_app: &App, model: &mut Model, event: WindowEvent) {
+// Grows a single strike as fast as possible without a window and saves it with the CPU renderer.
+fn capture_headless(path: &Path, generator: Generator) {
+    let mut model = Model::new();
+    model.generator = generator;
+    model.reset();
+    // Steps as if running at 60 frames a second, giving up if nothing reaches the ground.
+    for _ in 0..HEADLESS_MAX_STEPS {
+        match model.generator {
+            Generator::RandomWalk => step_leaders(&mut model, 1.0 / 60.0),
+            Generator::Laplacian => grow_dbm(&mut model),
+        }
+        if model.grounded_leader.is_some() || !model.simulate {
+            break;
+        }
+    }
+    if model.grounded_leader.is_some() {
+        model.land(Instant::now().checked_sub(Duration::from_secs_f32(HEADLESS_SETTLE_SECONDS)));
+    }
+    match capture(&model, path) {
+        Ok(()) => println!("Saved {}", path.display()),
+        Err(e) => println!("Failed to save {}: {}", path.display(), e),
+    }
+}
+
+fn event(app: &App, model: &mut Model, event: WindowEvent) {
     match event {
+        WindowEvent::MousePressed(MouseButton::Left) => {
+            // New origins take effect on the next strike.
+            model.origins.push(vec2(app.mouse.x, CLOUD_Y));
+        }
+        WindowEvent::MousePressed(MouseButton::Right) => {
+            model.targets.push(Target::new(app.mouse.x, model.target_kind));
+            // The Laplacian field has to see it straight away, not just from the next strike.
+            if let Some(dbm) = &mut model.dbm {
+                let j = model.targets.len() - 1;
+                dbm.add_target(j, &model.targets[j]);
+            }
+        }
+        WindowEvent::KeyPressed(Key::T) => {
+            model.target_kind = model.target_kind.next();
+            println!("Placing: {:?}", model.target_kind);
+        }
+        WindowEvent::KeyPressed(Key::C) => {
+            model.origins = default_origins();
+            model.targets.clear();
+            model.reset();
+        }
+        WindowEvent::KeyPressed(Key::G) => {
+            model.generator = model.generator.next();
+            println!("Generator: {:?}", model.generator);
+            model.reset();
+        }
+        WindowEvent::KeyPressed(Key::LBracket) => {
+            model.eta = (model.eta - DBM_ETA_STEP).max(0.0);
+            println!("Eta: {}", model.eta);
+        }
+        WindowEvent::KeyPressed(Key::RBracket) => {
+            model.eta += DBM_ETA_STEP;
+            println!("Eta: {}", model.eta);
+        }
+        WindowEvent::KeyPressed(Key::B) => {
+            let settings = &mut model.bloom_settings;
+            settings.enabled = !settings.enabled;
+            if settings.enabled && model.bloom.is_none() {
+                model.bloom = Some(Bloom::new(&app.main_window()));
+            }
+            println!("Bloom: {}", settings.enabled);
+        }
+        WindowEvent::KeyPressed(Key::Up) => {
+            model.bloom_settings.strength += BLOOM_STRENGTH_STEP;
+            println!("Bloom strength: {}", model.bloom_settings.strength);
+        }
+        WindowEvent::KeyPressed(Key::Down) => {
+            model.bloom_settings.strength = (model.bloom_settings.strength - BLOOM_STRENGTH_STEP).max(0.0);
+            println!("Bloom strength: {}", model.bloom_settings.strength);
+        }
+        WindowEvent::KeyPressed(Key::Right) => {
+            model.bloom_settings.radius += BLOOM_RADIUS_STEP;
+            println!("Bloom radius: {}", model.bloom_settings.radius);
+        }
+        WindowEvent::KeyPressed(Key::Left) => {
+            model.bloom_settings.radius = (model.bloom_settings.radius - BLOOM_RADIUS_STEP).max(BLOOM_RADIUS_STEP);
+            println!("Bloom radius: {}", model.bloom_settings.radius);
+        }
+        WindowEvent::KeyPressed(Key::I) => {
+            model.bloom_settings.tint = !model.bloom_settings.tint;
+            println!("Bloom tint: {}", model.bloom_settings.tint);
+        }
+        WindowEvent::KeyPressed(Key::P) => {
+            let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            match app.assets_path() {
+                Ok(assets) => {
+                    let path = assets.join(CAPTURE_DIR).join(format!("capture-{}.png", secs));
+                    match capture(model, &path) {
+                        Ok(()) => println!("Saved {}", path.display()),
+                        Err(e) => println!("Failed to save {}: {}", path.display(), e),
+                    }
+                }
+                Err(e) => println!("No assets folder to save to: {}", e),
+            }
+        }
         WindowEvent::KeyPressed(Key::Space) => {
             model.simulate = !model.simulate;
         }
         WindowEvent::KeyPressed(Key::R) => {
             model.reset();
         }
+        WindowEvent::KeyPressed(Key::S) => {
+            model.storm.enabled = !model.storm.enabled;
+            println!("Storm: {}", model.storm.enabled);
+        }
+        WindowEvent::KeyPressed(Key::W) => {
+            model.storm.write_thunder = !model.storm.write_thunder;
+            println!("Write thunder: {}", model.storm.write_thunder);
+        }
         _ => {}
     }
 }
 
-fn update(_app: &App, model: &mut Model, update: Update) {
-    if !model.simulate || model.grounded_leader.is_some() {
-        return;
+fn update(app: &App, model: &mut Model, update: Update) {
+    if model.simulate && model.grounded_leader.is_none() {
+        match model.generator {
+            Generator::RandomWalk => step_leaders(model, update.since_last.as_secs_f32()),
+            Generator::Laplacian => grow_dbm(model),
+        }
+        if model.grounded_leader.is_some() {
+            model.land(Some(Instant::now()));
+        }
     }
+    if model.storm.enabled {
+        storm_step(app, model, update.since_last.as_secs_f32());
+    }
+    for leader in &mut model.leaders {
+        leader.tessellate();
+    }
+    model.storm.fading.retain(|(_, at)| Storm::fade(at.elapsed()) > 0.0);
+    model.storm.cloud_flashes.retain(|flash| flash.life() > 0.0);
+
+    if model.bloom_settings.enabled {
+        if let Some(bloom) = &model.bloom {
+            bloom.draw.reset();
+            draw_scene(&bloom.draw, model);
+        }
+        let flash = model.flash();
+        if let Some(bloom) = &mut model.bloom {
+            bloom.render(&app.main_window(), &model.bloom_settings, flash);
+        }
+    }
+}
+
+// Thunders for a new strike and schedules the next one, and sets off the odd cloud flash.
+fn storm_step(app: &App, model: &mut Model, dt: f32) {
+    // Only ground strikes thunder, so a storm left running doesn't write files several times a
+    // second.
+    if let (Some(strike), None) = (&model.strike, model.storm.next_strike) {
+        emit_thunder(app, &mut model.storm, strike.path.clone());
+        let delay = random_range(STORM_MIN_DELAY, STORM_MAX_DELAY);
+        model.storm.next_strike = Some(Instant::now() + Duration::from_secs_f32(delay));
+    }
+    if model.storm.next_strike.is_some_and(|at| Instant::now() >= at) {
+        model.next_strike();
+    }
+
+    if random_f32() < CLOUD_FLASH_RATE * dt {
+        model.storm.cloud_flashes.push(CloudFlash::new());
+    }
+}
+
+// Grows the Laplacian channel a few cells, settling the field a little in between so it keeps up.
+fn grow_dbm(model: &mut Model) {
+    let dbm = match &mut model.dbm {
+        Some(dbm) => dbm,
+        None => return,
+    };
+    for _ in 0..DBM_GROWTH_PER_UPDATE {
+        dbm.relax(DBM_SWEEPS_PER_GROWTH);
+        match dbm.grow(&mut model.leaders, model.eta) {
+            Growth::Grew => {}
+            Growth::Grounded(leader, target) => {
+                model.grounded_leader = Some(leader);
+                model.struck_target = target;
+                println!("Done after {} branches", model.leaders.len());
+                return;
+            }
+            Growth::Stuck => {
+                println!("Nowhere left to grow");
+                // A storm just moves on to the next strike.
+                if model.storm.enabled {
+                    model.next_strike();
+                } else {
+                    model.simulate = false;
+                }
+                return;
+            }
+        }
+    }
+}
 
+fn step_leaders(model: &mut Model, dt: f32) {
     // For each leader, see if we're gonna split.
     let n = model.leaders.len();
     for i in 0..n {
         // If we split, then create a new leader.
         if random_f32() < LEADER_SPLIT_CHANCE {
-            // TODO: Save out current leader's path index so we can walk up for the grounded strike.
+            // We start partway along our parent's last segment, so only the points before it lead up
+            // to us.
             let new_leader = Leader::new(
                 model.leaders[i].pos(),
                 // Branch off in a random direction.
                 // TODO: Maybe enforece a minimum angle?
                 model.leaders[i].dir.angle().to_degrees() + random_range(MIN_TURN_DEGREES, MAX_TURN_DEGREES),
-                Some(i),
+                Some((i, model.leaders[i].path.len() - 1)),
             );
             model.leaders.push(new_leader);
         }
     }
 
-    // Step each leader. If one reached the ground (bottom of the screen), then we're done.
+    // Step each leader, leaning toward the closest target in reach. If one touched a target or
+    // reached the ground (bottom of the screen), then we're done.
+    let targets = &mut model.targets;
     for (i, leader) in model.leaders.iter_mut().enumerate() {
-        leader.step(update.since_last.as_secs_f32());
-        if leader.pos().y < -HEIGHT / 2.0 {
+        let pos = leader.pos();
+        let closest = targets.iter()
+            .enumerate()
+            .filter(|(_, target)| target.tip().distance(pos) < target.kind.attract_radius())
+            .min_by(|(_, a), (_, b)| a.tip().distance(pos).partial_cmp(&b.tip().distance(pos)).unwrap())
+            .map(|(j, _)| j);
+        leader.step(dt, closest.map(|j| targets[j].tip()));
+
+        let struck = targets.iter()
+            .position(|target| target.touches(leader.pos()) || target.tip().distance(leader.pos()) < CONNECT_DIST);
+        if let Some(j) = struck {
+            // Join up with the streamer so the strike reaches all the way down.
+            let target = &targets[j];
+            leader.path.push(target.tip());
+            leader.path.extend(target.streamer.iter().rev().skip(1));
+            model.grounded_leader = Some(i);
+            model.struck_target = Some(j);
+            println!("Struck {:?} after {} iterations", target.kind, leader.path.len());
+            return;
+        }
+        if leader.pos().y < GROUND_Y {
             model.grounded_leader = Some(i);
             println!("Done after {} iterations", leader.path.len());
-            break;
+            return;
+        }
+    }
+
+    // Targets with a leader in reach send a streamer up toward the closest one.
+    for target in &mut model.targets {
+        let top = target.top();
+        let closest = model.leaders.iter()
+            .map(Leader::pos)
+            .filter(|pos| pos.distance(top) < target.kind.attract_radius())
+            .min_by(|a, b| a.distance(top).partial_cmp(&b.distance(top)).unwrap());
+        if let Some(pos) = closest {
+            target.grow_streamer(pos, dt);
         }
     }
 }
 
 fn view(app: &App, model: &Model, frame: Frame) {
-    let draw = app.draw();
-
-    draw.background()
-        //.color(Rgb::new(230u8, 230, 250));
-        .color(BLACK);
-
-    // For each leader, draw all their line segments.
-    for leader in &model.leaders {
-        // TODO: Determine a measure based on spawn time (and path length? maybe total length
-        // traveled?)
-        let stroke_weight = map_range(clamp(leader.path.len(), SHORT_PATH, LONG_PATH), SHORT_PATH, LONG_PATH, 0.2, 3.0);
-        let alpha = map_range(clamp(leader.path.len(), SHORT_PATH, LONG_PATH), SHORT_PATH, LONG_PATH, 0.3, 1.0);
-        let color = Rgba::new(1.0, 1.0, 1.0, alpha);
-
-        draw.polyline()
-            .caps_square()
-            .stroke_weight(stroke_weight)
-            // TODO: Can we not clone everything?
-            .points(leader.path.iter().cloned())
-            .color(color);
+    if let (true, Some(bloom)) = (model.bloom_settings.enabled, &model.bloom) {
+        // Bloom was already rendered during update, just copy it over.
+        let mut encoder = frame.command_encoder();
+        bloom.reshaper.encode_render_pass(frame.texture_view(), &mut encoder);
+        return;
     }
 
-    // TODO: For the grounded leader, draw with a thicker stroke all the way back up.
-    if let Some(_leader_id) = model.grounded_leader {
+    let draw = app.draw();
+    draw_scene(&draw, model);
+    let flash = model.flash();
+    if flash > 0.0 {
+        draw.color_blend(BLEND_ADD)
+            .rect()
+            .w_h(WIDTH, HEIGHT)
+            .color(lin_srgba(1.0, 1.0, 1.0, flash));
     }
 
     draw.to_frame(app, &frame)
@@ -210,6 +1804,20 @@ fn view(app: &App, model: &Model, frame: Frame) {
 }
 
 fn main() {
+    // `--capture <path> [laplacian]` skips the window, grows one strike and saves it as a PNG.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("--capture") {
+        let generator = match args.get(2).map(String::as_str) {
+            Some("laplacian") => Generator::Laplacian,
+            _ => Generator::RandomWalk,
+        };
+        match args.get(1) {
+            Some(path) => capture_headless(Path::new(path), generator),
+            None => println!("Usage: 008_lightning --capture <path> [laplacian]"),
+        }
+        return;
+    }
+
     nannou::app(model)
         .update(update)
         .run();
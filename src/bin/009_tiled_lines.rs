@@ -1,6 +1,9 @@
 // Based on Tiled Lines from Generative Artistry:
 // https://generativeartistry.com/tutorials/tiled-lines/
 
+use std::collections::HashSet;
+
+use nannou::noise::{Fbm, MultiFractal, NoiseFn};
 use nannou::prelude::*;
 use nannou::winit::event::VirtualKeyCode;
 use nannou::Draw;
@@ -9,9 +12,165 @@ const WIDTH: f32 = 320.0;
 const HEIGHT: f32 = 320.0;
 
 const STEP: usize = 20;
+const STEPS: &[usize] = &[8, 10, 16, 20, 32, 40];
+const MARGIN: f32 = 20.0;
+
+const STROKE_WEIGHT: f32 = 3.0;
+const ARC_RESOLUTION: usize = 12;
+const NOISE_SCALE: f64 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TileRule {
+    // One of the two diagonals with equal chance.
+    Diagonal,
+    // One of the two diagonals, favoring one by `Settings::weight`.
+    WeightedDiagonal,
+    // Quarter arcs joining the midpoints of adjacent edges.
+    Truchet,
+    // The C64 classic: 10 PRINT CHR$(205.5+RND(1)); : GOTO 10
+    TenPrint,
+    // Diagonals that line up across tiles are merged into longer polylines.
+    Joined,
+}
+
+impl TileRule {
+    fn from_key(key: VirtualKeyCode) -> Option<Self> {
+        match key {
+            VirtualKeyCode::Key1 => Some(TileRule::Diagonal),
+            VirtualKeyCode::Key2 => Some(TileRule::WeightedDiagonal),
+            VirtualKeyCode::Key3 => Some(TileRule::Truchet),
+            VirtualKeyCode::Key4 => Some(TileRule::TenPrint),
+            VirtualKeyCode::Key5 => Some(TileRule::Joined),
+            _ => None,
+        }
+    }
+}
+
+// The tile layout inside the window. Tile (0, 0) is the top left one.
+struct Grid {
+    left: f32,
+    top: f32,
+    step: f32,
+    cols: usize,
+    rows: usize,
+}
+
+impl Grid {
+    fn new(step: usize, margin: f32) -> Self {
+        let cols = ((WIDTH - 2.0 * margin) / step as f32) as usize;
+        let rows = ((HEIGHT - 2.0 * margin) / step as f32) as usize;
+        let step = step as f32;
+        // Center the tiles so any leftover space is split evenly.
+        Self {
+            left: -(cols as f32 * step) / 2.0,
+            top: rows as f32 * step / 2.0,
+            step,
+            cols,
+            rows,
+        }
+    }
+
+    fn top_left(&self, col: usize, row: usize) -> Vec2 {
+        vec2(self.left + col as f32 * self.step, self.top - row as f32 * self.step)
+    }
+
+    // Calls f with the top left corner and flip of every tile, row by row.
+    fn for_each_tile(&self, tiles: &[bool], mut f: impl FnMut(Vec2, bool)) {
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                f(self.top_left(col, row), tiles[row * self.cols + col]);
+            }
+        }
+    }
+}
+
+// Everything that decides how a composition is generated.
+#[derive(Clone, Copy, Debug)]
+struct Settings {
+    rule: TileRule,
+    step: usize,
+    use_margin: bool,
+    // Chance of picking the "flipped" variant of a tile.
+    weight: f32,
+    // Vary the chance across the canvas with noise instead of using a fixed weight.
+    use_noise: bool,
+}
+
+impl Settings {
+    fn grid(&self) -> Grid {
+        let margin = if self.use_margin { MARGIN } else { 0.0 };
+        Grid::new(self.step, margin)
+    }
+
+    fn flip_chance(&self, noise: &Fbm, pos: Vec2, noise_z: f64) -> f32 {
+        if self.use_noise {
+            let value = noise.get([pos.x as f64 * NOISE_SCALE, pos.y as f64 * NOISE_SCALE, noise_z]);
+            map_range(value as f32, -1.0, 1.0, 0.0, 1.0)
+        } else if self.rule == TileRule::WeightedDiagonal {
+            self.weight
+        } else {
+            0.5
+        }
+    }
+}
+
+// A generated image: the settings it was made with and the variant picked for every tile.
+struct Composition {
+    settings: Settings,
+    // Row-major, true for the "flipped" variant.
+    tiles: Vec<bool>,
+}
+
+impl Composition {
+    fn generate(settings: Settings, noise: &Fbm) -> Self {
+        let grid = settings.grid();
+        let noise_z = random_range(0.0, 1000.0);
+        let mut tiles = Vec::with_capacity(grid.cols * grid.rows);
+        for row in 0..grid.rows {
+            for col in 0..grid.cols {
+                let center = grid.top_left(col, row) + vec2(grid.step, -grid.step) / 2.0;
+                tiles.push(random_f32() < settings.flip_chance(noise, center, noise_z));
+            }
+        }
+
+        Self {
+            settings,
+            tiles,
+        }
+    }
+}
 
 struct Model {
-    draw_frame: u64,
+    settings: Settings,
+    // Plain Perlin noise. noise::Perlin is ambiguous with the surflet version, so it comes
+    // through a one octave Fbm instead.
+    noise: Fbm,
+
+    // Every composition generated so far, and which one is on screen.
+    history: Vec<Composition>,
+    current: usize,
+
+    // Keys currently held down, so key repeats can be ignored.
+    keys_down: HashSet<VirtualKeyCode>,
+}
+
+impl Model {
+    // Generates a new composition from the current settings and shows it.
+    fn regenerate(&mut self) {
+        self.history.push(Composition::generate(self.settings, &self.noise));
+        self.current = self.history.len() - 1;
+    }
+
+    // Moves through history by offset, picking up the settings of the composition we land on.
+    fn navigate(&mut self, offset: isize) {
+        let target = self.current as isize + offset;
+        if target < 0 || target >= self.history.len() as isize {
+            return;
+        }
+        self.current = target as usize;
+        self.settings = self.history[self.current].settings;
+        println!("Composition {}/{}", self.current + 1, self.history.len());
+    }
 }
 
 fn model(app: &App) -> Model {
@@ -24,58 +183,227 @@ fn model(app: &App) -> Model {
         .size_pixels(WIDTH as u32, HEIGHT as u32)
         .title("Tiled Lines")
         .key_pressed(key_pressed)
+        .key_released(key_released)
+        .unfocused(unfocused)
         .view(view)
         .build()
         .unwrap();
 
-    Model {
-        draw_frame: 1,
-    }
+    let mut model = Model {
+        settings: Settings {
+            rule: TileRule::Diagonal,
+            step: STEP,
+            use_margin: false,
+            weight: 0.75,
+            use_noise: false,
+        },
+        noise: Fbm::new().set_octaves(1),
+
+        history: Vec::new(),
+        current: 0,
+
+        keys_down: HashSet::new(),
+    };
+    model.regenerate();
+    model
 }
 
-fn key_pressed(app: &App, model: &mut Model, key: VirtualKeyCode) {
-    // TODO: Ignore repeat key presses. Ughhh.
+fn key_pressed(_app: &App, model: &mut Model, key: VirtualKeyCode) {
+    // Holding a key down sends repeated presses. Only react to the first one.
+    if !model.keys_down.insert(key) {
+        return;
+    }
+
+    let settings = &mut model.settings;
+
+    if let Some(rule) = TileRule::from_key(key) {
+        settings.rule = rule;
+        println!("Tile rule: {:?}", rule);
+        model.regenerate();
+        return;
+    }
+
     match key {
         VirtualKeyCode::Space => {
-            // Set to next frame since it will increment before view is called.
-            model.draw_frame = app.elapsed_frames() + 1;
+            model.regenerate();
+        }
+        VirtualKeyCode::Left => {
+            model.navigate(-1);
+        }
+        VirtualKeyCode::Right => {
+            model.navigate(1);
+        }
+        VirtualKeyCode::Up | VirtualKeyCode::Down => {
+            let i = STEPS.iter().position(|&step| step == settings.step).unwrap_or(0);
+            let i = if key == VirtualKeyCode::Up {
+                (i + 1).min(STEPS.len() - 1)
+            } else {
+                i.saturating_sub(1)
+            };
+            settings.step = STEPS[i];
+            println!("Step: {}", settings.step);
+            model.regenerate();
+        }
+        VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+            let delta = if key == VirtualKeyCode::RBracket { 0.05 } else { -0.05 };
+            settings.weight = clamp(settings.weight + delta, 0.0, 1.0);
+            println!("Weight: {:.2}", settings.weight);
+            model.regenerate();
+        }
+        VirtualKeyCode::M => {
+            settings.use_margin = !settings.use_margin;
+            model.regenerate();
+        }
+        VirtualKeyCode::N => {
+            settings.use_noise = !settings.use_noise;
+            println!("Noise: {}", settings.use_noise);
+            model.regenerate();
         }
         _ => {}
     }
 }
 
+fn key_released(_app: &App, model: &mut Model, key: VirtualKeyCode) {
+    model.keys_down.remove(&key);
+}
+
+fn unfocused(_app: &App, model: &mut Model) {
+    // We won't hear about keys released while another window has focus.
+    model.keys_down.clear();
+}
+
 fn update(_app: &App, _model: &mut Model, _update: Update) {
 }
 
-fn draw_line(draw: &Draw, x: f32, y: f32, width: f32, height: f32) {
+fn draw_line(draw: &Draw, top_left: Vec2, step: f32, flip: bool, weight: f32, color: Rgb<u8>) {
+    let (x, y) = (top_left.x, top_left.y);
     // Always draw top down.
-    let (start, end) = if random::<bool>() {
+    let (start, end) = if !flip {
         // Go left to right.
-        (vec2(x, y), vec2(x + width, y + height))
+        (vec2(x, y), vec2(x + step, y - step))
     } else {
         // Go right to left.
-        (vec2(x + width, y), vec2(x, y + height))
+        (vec2(x + step, y), vec2(x, y - step))
     };
     draw.line()
         .start(start)
         .end(end)
-        .stroke_weight(3.0)
+        .stroke_weight(weight)
+        .color(color);
+}
+
+// Draws a quarter circle around center, starting at start_angle and sweeping counter-clockwise.
+fn draw_arc(draw: &Draw, center: Vec2, radius: f32, start_angle: f32) {
+    let points = (0..=ARC_RESOLUTION).map(|i| {
+        let angle = start_angle + i as f32 / ARC_RESOLUTION as f32 * PI / 2.0;
+        center + vec2(angle.cos(), angle.sin()) * radius
+    });
+    draw.polyline()
+        .stroke_weight(STROKE_WEIGHT)
+        .points(points)
         .color(BLACK);
 }
 
-fn view(app: &App, model: &Model, frame: Frame) {
-    if model.draw_frame != app.elapsed_frames() {
-        return;
+fn draw_truchet(draw: &Draw, top_left: Vec2, step: f32, flip: bool) {
+    let radius = step / 2.0;
+    let top_right = top_left + vec2(step, 0.0);
+    let bottom_left = top_left + vec2(0.0, -step);
+    let bottom_right = top_left + vec2(step, -step);
+    if !flip {
+        draw_arc(draw, top_left, radius, -PI / 2.0);
+        draw_arc(draw, bottom_right, radius, PI / 2.0);
+    } else {
+        draw_arc(draw, top_right, radius, PI);
+        draw_arc(draw, bottom_left, radius, 0.0);
     }
+}
+
+// Walks chains of diagonals that meet corner to corner and draws each chain as one polyline.
+fn draw_joined(draw: &Draw, grid: &Grid, tiles: &[bool]) {
+    let index = |col: usize, row: usize| row * grid.cols + col;
+    // A "\" tile continues down-right, a "/" tile continues down-left.
+    let next_col = |col: usize, flip: bool| -> Option<usize> {
+        if flip {
+            col.checked_sub(1)
+        } else if col + 1 < grid.cols {
+            Some(col + 1)
+        } else {
+            None
+        }
+    };
+    let prev_col = |col: usize, flip: bool| next_col(col, !flip);
+
+    for row in 0..grid.rows {
+        for col in 0..grid.cols {
+            let flip = tiles[index(col, row)];
+
+            // Only start walking from the first tile of a chain.
+            let continues_chain = row > 0 && prev_col(col, flip)
+                .is_some_and(|prev| tiles[index(prev, row - 1)] == flip);
+            if continues_chain {
+                continue;
+            }
+
+            let start = grid.top_left(col, row) + if flip { vec2(grid.step, 0.0) } else { Vec2::ZERO };
+            let mut points = vec![start];
+            let (mut c, mut r) = (col, row);
+            loop {
+                let corner = grid.top_left(c, r) + if flip { vec2(0.0, -grid.step) } else { vec2(grid.step, -grid.step) };
+                points.push(corner);
+                match next_col(c, flip) {
+                    Some(next) if r + 1 < grid.rows && tiles[index(next, r + 1)] == flip => {
+                        c = next;
+                        r += 1;
+                    }
+                    _ => break,
+                }
+            }
 
+            draw.polyline()
+                .caps_round()
+                .join_round()
+                .stroke_weight(STROKE_WEIGHT)
+                .points(points)
+                .color(BLACK);
+        }
+    }
+}
+
+fn view(app: &App, model: &Model, frame: Frame) {
     let draw = app.draw();
 
-    draw.background()
-        .color(WHITE);
+    let composition = &model.history[model.current];
+    let rule = composition.settings.rule;
+    let grid = composition.settings.grid();
+    let tiles = &composition.tiles;
 
-    for x in (-WIDTH as i32 / 2..WIDTH as i32 / 2).step_by(STEP) {
-        for y in (-HEIGHT as i32 / 2..HEIGHT as i32 / 2).rev().step_by(STEP) {
-            draw_line(&draw, x as f32, y as f32, STEP as f32, -(STEP as f32));
+    if rule == TileRule::TenPrint {
+        draw.background()
+            .color(Rgb::new(0x40u8, 0x31, 0x8d));
+    } else {
+        draw.background()
+            .color(WHITE);
+    }
+
+    match rule {
+        // Whole lines across tiles rather than one tile at a time.
+        TileRule::Joined => draw_joined(&draw, &grid, tiles),
+        TileRule::Diagonal | TileRule::WeightedDiagonal => {
+            let black = Rgb::new(0u8, 0, 0);
+            grid.for_each_tile(tiles, |top_left, flip| {
+                draw_line(&draw, top_left, grid.step, flip, STROKE_WEIGHT, black);
+            });
+        }
+        TileRule::Truchet => {
+            grid.for_each_tile(tiles, |top_left, flip| {
+                draw_truchet(&draw, top_left, grid.step, flip);
+            });
+        }
+        TileRule::TenPrint => {
+            let light_blue = Rgb::new(0x78u8, 0x69, 0xc4);
+            grid.for_each_tile(tiles, |top_left, flip| {
+                draw_line(&draw, top_left, grid.step, flip, grid.step / 4.0, light_blue);
+            });
         }
     }
 
@@ -1,78 +1,1542 @@
 // Based on the Processing Flocking example: https://processing.org/examples/flocking.html
 // Trying to mimic the look and feel of: https://www.youtube.com/watch?v=QbUPfMXXQIY
 
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use nannou::prelude::*;
-use nannou::color::Gradient;
+use nannou::color::{Gradient, IntoLinSrgba};
+use nannou::rand::rand::{
+    rngs::StdRng,
+    Rng,
+    SeedableRng,
+};
+
+const WIDTH: f32 = 800.0;
+const HEIGHT: f32 = 600.0;
+
+const INITIAL_BOID_COUNT: u32 = 150;
+const INITIAL_PREDATOR_COUNT: u32 = 3;
+const BOID_RADIUS: f32 = 4.0;
+const MAX_SPEED: f32 = 2.0;
+const MAX_FORCE: f32 = 0.03;
+
+const TWO_PI: f32 = 2.0 * std::f32::consts::PI;
+
+// How far ahead, in frames of movement, boids check for obstacles.
+const LOOK_AHEAD_FRAMES: f32 = 25.0;
+// Number of points checked along the look ahead.
+const LOOK_AHEAD_PROBES: usize = 5;
+const SEPARATION_DIST: f32 = 25.0;
+const NEIGHBOR_DIST: f32 = 50.0;
+// How wide a cone boids see ahead of them by default, in degrees. Anything less than a full circle
+// leaves a blind spot behind them.
+const DEFAULT_FOV_DEGREES: f32 = 360.0;
+// How much each press of [ or ] changes a view angle, in degrees.
+const FOV_STEP_DEGREES: f32 = 15.0;
+
+// In soft walls mode, boids start turning back this far from the window border.
+const WALL_MARGIN: f32 = 50.0;
+const WALL_FORCE: f32 = 0.1;
+// How quickly the camera catches up to the flock in open mode. Higher is snappier.
+const CAMERA_FOLLOW: f32 = 0.05;
+// Spacing of the background grid in open mode, so there's something to see the camera move against.
+const GRID_SPACING: f32 = 100.0;
+
+// How many past positions each boid remembers for its trail.
+const TRAIL_LENGTH: usize = 30;
+// Width of the cells boids are splatted into for the density heatmap.
+const HEATMAP_CELL: f32 = 20.0;
+// Cell density that shows up at full brightness.
+const HEATMAP_MAX_DENSITY: f32 = 4.0;
+
+// Predators eat prey closer than this.
+const EAT_DIST: f32 = 2.0 * BOID_RADIUS;
+// Followers aim for a point this far behind the leader.
+const LEADER_FOLLOW_DIST: f32 = 30.0;
+
+// Clicking within this many pixels of an obstacle picks it.
+const PICK_DIST: f32 = 4.0;
+
+// New force nodes start out with these.
+const DEFAULT_NODE_RADIUS: f32 = 50.0;
+const DEFAULT_NODE_STRENGTH: f32 = 2.0;
+// How much the arrow keys change the selected node each press.
+const NODE_RADIUS_STEP: f32 = 10.0;
+const NODE_STRENGTH_STEP: f32 = 0.25;
+// Inverse square forces are capped at what they'd be this close, so they don't blow up.
+const NODE_SOFTENING: f32 = 10.0;
+// Clicking within this many pixels of a node's center picks it.
+const NODE_PICK_DIST: f32 = 8.0;
+// Relative to the assets folder.
+const STATE_FILE: &str = "boids/state.txt";
+// Telemetry files go in here, relative to the assets folder, named after when recording started.
+const TELEMETRY_DIR: &str = "boids/telemetry";
+// Same for recordings of runs, and the frames rendered from replaying them.
+const RECORDING_DIR: &str = "boids/recordings";
+const FRAMES_DIR: &str = "boids/frames";
+// How many steps Page Up and Page Down seek in a replay.
+const SEEK_STEPS: u64 = 100;
+
+// How many steps of statistics the sparklines show.
+const STATS_HISTORY: usize = 300;
+// Neighbor counts are bucketed 0, 1, ... up to this many or more.
+const NEIGHBOR_BUCKETS: usize = 9;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BoundaryMode {
+    // Leave one side of the window and come back in on the other. Boids see each other across
+    // the seam.
+    Wrap,
+    // Reflect off the window border.
+    Bounce,
+    // Steer away from the window border when getting close.
+    SoftWalls,
+    // No border at all. The camera follows the middle of the flock instead.
+    Open,
+}
+
+impl BoundaryMode {
+    fn next(self) -> Self {
+        match self {
+            BoundaryMode::Wrap => BoundaryMode::Bounce,
+            BoundaryMode::Bounce => BoundaryMode::SoftWalls,
+            BoundaryMode::SoftWalls => BoundaryMode::Open,
+            BoundaryMode::Open => BoundaryMode::Wrap,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            BoundaryMode::Wrap => "wrap",
+            BoundaryMode::Bounce => "bounce",
+            BoundaryMode::SoftWalls => "soft_walls",
+            BoundaryMode::Open => "open",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wrap" => Some(BoundaryMode::Wrap),
+            "bounce" => Some(BoundaryMode::Bounce),
+            "soft_walls" => Some(BoundaryMode::SoftWalls),
+            "open" => Some(BoundaryMode::Open),
+            _ => None,
+        }
+    }
+
+    // The shortest vector from one position to another. When wrapping, that may cross the seam.
+    fn offset(self, from: Vec2, to: Vec2) -> Vec2 {
+        let offset = to - from;
+        if self != BoundaryMode::Wrap {
+            return offset;
+        }
+        // Boids wrap once they're fully past the border, so the world is a little bigger than the
+        // window.
+        let size = vec2(WIDTH, HEIGHT) + Vec2::splat(2.0 * BOID_RADIUS);
+        let wrap = |d: f32, size: f32| (d + size / 2.0).rem_euclid(size) - size / 2.0;
+        vec2(wrap(offset.x, size.x), wrap(offset.y, size.y))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Species {
+    Prey,
+    Predator,
+}
+
+struct Boid {
+    pos: Vec2,
+    vel: Vec2,
+    species: Species,
+    // Most recent position first.
+    trail: VecDeque<Vec2>,
+}
+
+impl Boid {
+    fn new(x: f32, y: f32, species: Species, rng: &mut StdRng) -> Self {
+        let angle = rng.gen_range(0.0..TWO_PI);
+        Self {
+            pos: vec2(x, y),
+            vel: vec2(angle.cos(), angle.sin()),
+            species,
+            trail: VecDeque::with_capacity(TRAIL_LENGTH),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RenderMode {
+    // Prey go from sad to happy the more neighbors they have.
+    Neighbors,
+    // Fading lines behind each boid.
+    Trails,
+    // Hue shows which way each boid is heading.
+    Heading,
+    // Brightness shows how fast each boid is going.
+    Speed,
+    // How crowded each part of the window is.
+    Heatmap,
+    // Each connected group of prey gets its own color.
+    Clusters,
+}
+
+impl RenderMode {
+    fn next(self) -> Self {
+        match self {
+            RenderMode::Neighbors => RenderMode::Trails,
+            RenderMode::Trails => RenderMode::Heading,
+            RenderMode::Heading => RenderMode::Speed,
+            RenderMode::Speed => RenderMode::Heatmap,
+            RenderMode::Heatmap => RenderMode::Clusters,
+            RenderMode::Clusters => RenderMode::Neighbors,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            RenderMode::Neighbors => "neighbors",
+            RenderMode::Trails => "trails",
+            RenderMode::Heading => "heading",
+            RenderMode::Speed => "speed",
+            RenderMode::Heatmap => "heatmap",
+            RenderMode::Clusters => "clusters",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "neighbors" => Some(RenderMode::Neighbors),
+            "trails" => Some(RenderMode::Trails),
+            "heading" => Some(RenderMode::Heading),
+            "speed" => Some(RenderMode::Speed),
+            "heatmap" => Some(RenderMode::Heatmap),
+            "clusters" => Some(RenderMode::Clusters),
+            _ => None,
+        }
+    }
+}
+
+// Measurements of the prey flock at one step.
+#[derive(Clone, Debug)]
+struct FlockStats {
+    // How well the flock is lined up: 1 when every boid heads the same way, near 0 when they head
+    // every which way.
+    polarization: f32,
+    // Average distance from each boid to its closest neighbor.
+    mean_nearest_dist: f32,
+    clusters: usize,
+    avg_speed: f32,
+    // How many boids have 0, 1, 2... neighbors within NEIGHBOR_DIST. The last bucket counts all the
+    // rest.
+    neighbor_counts: [u32; NEIGHBOR_BUCKETS],
+}
+
+impl FlockStats {
+    fn mean_neighbors(&self) -> f32 {
+        let total: u32 = self.neighbor_counts.iter().sum();
+        if total == 0 {
+            return 0.0;
+        }
+        let sum: u32 = self.neighbor_counts.iter().enumerate().map(|(n, &count)| n as u32 * count).sum();
+        sum as f32 / total as f32
+    }
+
+    fn csv_header() -> String {
+        let buckets = (0..NEIGHBOR_BUCKETS).map(|n| {
+            if n + 1 == NEIGHBOR_BUCKETS { format!(",neighbors_{}_plus", n) } else { format!(",neighbors_{}", n) }
+        });
+        std::iter::once("step,prey,polarization,mean_nearest_dist,clusters,avg_speed".to_string())
+            .chain(buckets)
+            .collect()
+    }
+
+    fn csv_line(&self, step: u64) -> String {
+        let prey: u32 = self.neighbor_counts.iter().sum();
+        let mut line = format!(
+            "{},{},{},{},{},{}",
+            step, prey, self.polarization, self.mean_nearest_dist, self.clusters, self.avg_speed,
+        );
+        for count in &self.neighbor_counts {
+            line += &format!(",{}", count);
+        }
+        line
+    }
+}
+
+// Follows parent links to the root of i's set, flattening the path on the way.
+fn find_root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+// Whether a boid offset by to_other from boid is inside boid's view cone, which is fov radians wide
+// and centered on its heading.
+fn can_see(boid: &Boid, to_other: Vec2, fov: f32) -> bool {
+    if fov >= TWO_PI {
+        return true;
+    }
+    if to_other == Vec2::ZERO || boid.vel == Vec2::ZERO {
+        return true;
+    }
+    boid.vel.angle_between(to_other).abs() <= fov / 2.0
+}
+
+fn initial_boids(rng: &mut StdRng) -> Vec<Boid> {
+    let mut boids: Vec<Boid> = (0..INITIAL_BOID_COUNT)
+        .map(|_| Boid::new(0.0, 0.0, Species::Prey, rng))
+        .collect();
+    // Start predators off in the corners so the prey get a head start.
+    for _ in 0..INITIAL_PREDATOR_COUNT {
+        let x = if rng.gen() { -WIDTH / 2.0 } else { WIDTH / 2.0 };
+        let y = if rng.gen() { -HEIGHT / 2.0 } else { HEIGHT / 2.0 };
+        boids.push(Boid::new(x, y, Species::Predator, rng));
+    }
+    boids
+}
+
+// How strongly each species follows each rule, and how it moves and looks.
+struct SpeciesRules {
+    separation_factor: f32,
+    alignment_factor: f32,
+    cohesion_factor: f32,
+    repulsion_factor: f32,
+    avoidance_factor: f32,
+    // Prey flee predators and predators chase prey.
+    hunting_factor: f32,
+    goal_factor: f32,
+    leader_factor: f32,
+
+    max_speed: f32,
+    // How far away the other species is noticed.
+    view_radius: f32,
+    color: Rgba,
+}
+
+impl SpeciesRules {
+    fn prey() -> Self {
+        Self {
+            separation_factor: 1.5,
+            alignment_factor: 1.0,
+            cohesion_factor: 1.0,
+            repulsion_factor: 1.5,
+            avoidance_factor: 3.0,
+            hunting_factor: 3.0,
+            goal_factor: 0.5,
+            leader_factor: 1.0,
+
+            max_speed: MAX_SPEED,
+            view_radius: 80.0,
+            color: Rgba::new(95.0 / 255.0, 219.0 / 255.0, 0.0 / 255.0, 200.0 / 255.0),
+        }
+    }
+
+    fn predator() -> Self {
+        Self {
+            // Predators hunt alone, so they mostly just keep their distance from each other.
+            separation_factor: 2.0,
+            alignment_factor: 0.0,
+            cohesion_factor: 0.0,
+            repulsion_factor: 1.5,
+            avoidance_factor: 3.0,
+            hunting_factor: 1.5,
+            goal_factor: 0.0,
+            leader_factor: 0.0,
+
+            // A little faster than prey, but prey turn harder when fleeing.
+            max_speed: MAX_SPEED * 1.15,
+            view_radius: 150.0,
+            color: Rgba::new(230.0 / 255.0, 60.0 / 255.0, 40.0 / 255.0, 230.0 / 255.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NodeKind {
+    Repel,
+    Attract,
+}
+
+impl NodeKind {
+    fn name(self) -> &'static str {
+        match self {
+            NodeKind::Repel => "repel",
+            NodeKind::Attract => "attract",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [NodeKind::Repel, NodeKind::Attract].into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+// How a node's push or pull fades with distance. Both drop to nothing at the node's radius.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Falloff {
+    // Full strength at the center, fading evenly out to the radius.
+    Linear,
+    // Full strength within NODE_SOFTENING, then dropping with the square of the distance.
+    InverseSquare,
+}
+
+impl Falloff {
+    fn next(self) -> Self {
+        match self {
+            Falloff::Linear => Falloff::InverseSquare,
+            Falloff::InverseSquare => Falloff::Linear,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Falloff::Linear => "linear",
+            Falloff::InverseSquare => "inverse_square",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [Falloff::Linear, Falloff::InverseSquare].into_iter().find(|falloff| falloff.name() == name)
+    }
+}
+
+// A point that pushes boids away or pulls them in.
+struct Node {
+    pos: Vec2,
+    kind: NodeKind,
+    // Boids farther away than this don't feel the node at all.
+    radius: f32,
+    // At full strength, in units of MAX_FORCE.
+    strength: f32,
+    falloff: Falloff,
+}
+
+impl Node {
+    fn new(x: f32, y: f32, kind: NodeKind) -> Self {
+        Self {
+            pos: vec2(x, y),
+            kind,
+            radius: DEFAULT_NODE_RADIUS,
+            strength: DEFAULT_NODE_STRENGTH,
+            falloff: Falloff::Linear,
+        }
+    }
+
+    // The force on a boid offset by from_node from the node.
+    fn force(&self, from_node: Vec2) -> Vec2 {
+        let dist = from_node.length();
+        if dist == 0.0 || dist >= self.radius {
+            return Vec2::ZERO;
+        }
+        let scale = match self.falloff {
+            Falloff::Linear => 1.0 - dist / self.radius,
+            Falloff::InverseSquare => (NODE_SOFTENING / dist.max(NODE_SOFTENING)).powi(2),
+        };
+        let away = from_node / dist * self.strength * scale * MAX_FORCE;
+        match self.kind {
+            NodeKind::Repel => away,
+            NodeKind::Attract => -away,
+        }
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "node {} {} {} {} {} {}",
+            self.kind.name(), self.falloff.name(), self.pos.x, self.pos.y, self.radius, self.strength,
+        )
+    }
+}
+
+enum Obstacle {
+    Circle {
+        center: Vec2,
+        radius: f32,
+    },
+    Rect(Rect),
+    // Points in order around the outline. May be concave.
+    Polygon(Vec<Vec2>),
+}
+
+impl Obstacle {
+    // Corners of rects and polygons.
+    fn outline(&self) -> Vec<Vec2> {
+        match self {
+            Obstacle::Circle { .. } => Vec::new(),
+            Obstacle::Rect(rect) => vec![rect.bottom_left(), rect.bottom_right(), rect.top_right(), rect.top_left()],
+            Obstacle::Polygon(points) => points.clone(),
+        }
+    }
+
+    fn contains(&self, p: Vec2) -> bool {
+        match self {
+            Obstacle::Circle { center, radius } => p.distance(*center) < *radius,
+            Obstacle::Rect(rect) => rect.contains(p),
+            Obstacle::Polygon(points) => {
+                // Count edges crossed by a ray heading right from p.
+                let mut inside = false;
+                for (a, b) in edges(points) {
+                    if (a.y > p.y) != (b.y > p.y) {
+                        let x = a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                        if p.x < x {
+                            inside = !inside;
+                        }
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    // The closest point to p on the edge of the obstacle.
+    fn closest_edge_point(&self, p: Vec2) -> Vec2 {
+        match self {
+            Obstacle::Circle { center, radius } => {
+                let offset = p - *center;
+                let dir = if offset == Vec2::ZERO { Vec2::X } else { offset.normalize() };
+                *center + dir * *radius
+            }
+            _ => edges(&self.outline())
+                .map(|(a, b)| closest_point_on_segment(p, a, b))
+                .min_by(|a, b| a.distance(p).partial_cmp(&b.distance(p)).unwrap())
+                .unwrap_or(p),
+        }
+    }
+
+    // Unit vector pointing out of the obstacle from p, whether p is inside or not.
+    fn away(&self, p: Vec2) -> Vec2 {
+        let edge = self.closest_edge_point(p);
+        let dir = if self.contains(p) { edge - p } else { p - edge };
+        dir.normalize_or_zero()
+    }
+
+    fn translate(&mut self, delta: Vec2) {
+        match self {
+            Obstacle::Circle { center, .. } => *center += delta,
+            Obstacle::Rect(rect) => *rect = rect.shift(delta),
+            Obstacle::Polygon(points) => {
+                for p in points {
+                    *p += delta;
+                }
+            }
+        }
+    }
+
+    fn draw(&self, draw: &Draw, color: Rgba) {
+        match self {
+            Obstacle::Circle { center, radius } => {
+                draw.ellipse()
+                    .xy(*center)
+                    .radius(*radius)
+                    .color(color);
+            }
+            _ => {
+                draw.polygon()
+                    .color(color)
+                    .points(self.outline());
+            }
+        }
+    }
+
+    // One line of the state file.
+    fn to_line(&self) -> String {
+        match self {
+            Obstacle::Circle { center, radius } => format!("circle {} {} {}", center.x, center.y, radius),
+            Obstacle::Rect(rect) => format!("rect {} {} {} {}", rect.x(), rect.y(), rect.w(), rect.h()),
+            Obstacle::Polygon(points) => {
+                let coords: Vec<String> = points.iter().map(|p| format!("{} {}", p.x, p.y)).collect();
+                format!("polygon {}", coords.join(" "))
+            }
+        }
+    }
+}
+
+// Pairs of neighboring points, including the last back to the first.
+fn edges(points: &[Vec2]) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+    points.iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(&a, &b)| (a, b))
+}
+
+fn closest_point_on_segment(p: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let ab = b - a;
+    let len_sq = ab.length_squared();
+    if len_sq == 0.0 {
+        return a;
+    }
+    let t = clamp((p - a).dot(ab) / len_sq, 0.0, 1.0);
+    a + ab * t
+}
+
+// The rules that look at neighboring boids, each with its own view angle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NeighborRule {
+    Separation,
+    Alignment,
+    Cohesion,
+}
+
+impl NeighborRule {
+    fn name(self) -> &'static str {
+        match self {
+            NeighborRule::Separation => "separation",
+            NeighborRule::Alignment => "alignment",
+            NeighborRule::Cohesion => "cohesion",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "separation" => Some(NeighborRule::Separation),
+            "alignment" => Some(NeighborRule::Alignment),
+            "cohesion" => Some(NeighborRule::Cohesion),
+            _ => None,
+        }
+    }
+
+    fn radius(self) -> f32 {
+        match self {
+            NeighborRule::Separation => SEPARATION_DIST,
+            NeighborRule::Alignment | NeighborRule::Cohesion => NEIGHBOR_DIST,
+        }
+    }
+
+    fn debug_color(self) -> Rgba {
+        match self {
+            NeighborRule::Separation => Rgba::new(1.0, 0.4, 0.4, 1.0),
+            NeighborRule::Alignment => Rgba::new(0.4, 0.6, 1.0, 1.0),
+            NeighborRule::Cohesion => Rgba::new(1.0, 1.0, 0.4, 1.0),
+        }
+    }
+}
+
+const NEIGHBOR_RULES: [NeighborRule; 3] = [NeighborRule::Separation, NeighborRule::Alignment, NeighborRule::Cohesion];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EditTool {
+    Circle,
+    Rect,
+    Polygon,
+}
+
+impl EditTool {
+    fn next(self) -> Self {
+        match self {
+            EditTool::Circle => EditTool::Rect,
+            EditTool::Rect => EditTool::Polygon,
+            EditTool::Polygon => EditTool::Circle,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [EditTool::Circle, EditTool::Rect, EditTool::Polygon].into_iter().find(|tool| format!("{:?}", tool) == name)
+    }
+}
+
+// Keys that change the simulation, and so get recorded. Anything else is left out of recordings.
+const RECORDED_KEYS: [Key; 28] = [
+    Key::A, Key::B, Key::Back, Key::C, Key::E, Key::F, Key::G, Key::H, Key::I, Key::K, Key::M, Key::N,
+    Key::P, Key::Q, Key::R, Key::T, Key::V, Key::Return, Key::Delete, Key::Key1, Key::Key2, Key::Key3,
+    Key::LBracket, Key::RBracket, Key::Up, Key::Down, Key::Left, Key::Right,
+];
+
+// The window events the simulation responds to, in a form that can be written to a recording and
+// read back. Mouse positions are in window coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Input {
+    MouseMoved(Vec2),
+    MousePressed(MouseButton),
+    MouseReleased(MouseButton),
+    KeyPressed(Key),
+}
+
+impl Input {
+    fn from_event(event: &WindowEvent) -> Option<Self> {
+        let recorded_button = |button: &MouseButton| matches!(button, MouseButton::Left | MouseButton::Right);
+        match event {
+            WindowEvent::MouseMoved(pos) => Some(Input::MouseMoved(*pos)),
+            WindowEvent::MousePressed(button) if recorded_button(button) => Some(Input::MousePressed(*button)),
+            WindowEvent::MouseReleased(button) if recorded_button(button) => Some(Input::MouseReleased(*button)),
+            WindowEvent::KeyPressed(key) if RECORDED_KEYS.contains(key) => Some(Input::KeyPressed(*key)),
+            _ => None,
+        }
+    }
+
+    fn to_line(self) -> String {
+        match self {
+            Input::MouseMoved(pos) => format!("move {} {}", pos.x, pos.y),
+            Input::MousePressed(button) => format!("press {:?}", button),
+            Input::MouseReleased(button) => format!("release {:?}", button),
+            Input::KeyPressed(key) => format!("key {:?}", key),
+        }
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let button = |name: &str| match name {
+            "Left" => Some(MouseButton::Left),
+            "Right" => Some(MouseButton::Right),
+            _ => None,
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match *words.as_slice() {
+            ["move", x, y] => Some(Input::MouseMoved(vec2(x.parse().ok()?, y.parse().ok()?))),
+            ["press", name] => button(name).map(Input::MousePressed),
+            ["release", name] => button(name).map(Input::MouseReleased),
+            ["key", name] => RECORDED_KEYS.iter().copied().find(|key| format!("{:?}", key) == name).map(Input::KeyPressed),
+            _ => None,
+        }
+    }
+}
+
+// Everything needed to re-run a recorded simulation: where it started from and what the user did.
+struct Recording {
+    seed: u64,
+    // The model when recording started, as written by Model::snapshot.
+    snapshot: String,
+    // Inputs in the order they happened, each with the step it happened before.
+    inputs: Vec<(u64, Input)>,
+}
+
+impl Recording {
+    fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let mut seed = None;
+        let mut snapshot = String::new();
+        let mut inputs = Vec::new();
+        for line in contents.lines() {
+            if let Some(rest) = line.strip_prefix("seed ") {
+                seed = rest.trim().parse().ok();
+            } else if let Some(rest) = line.strip_prefix("input ") {
+                let (step, input) = rest.split_once(' ').ok_or_else(|| format!("Bad line: {}", line))?;
+                let step = step.parse().map_err(|_| format!("Bad line: {}", line))?;
+                let input = Input::from_line(input).ok_or_else(|| format!("Bad line: {}", line))?;
+                inputs.push((step, input));
+            } else {
+                snapshot += line;
+                snapshot += "\n";
+            }
+        }
+        let seed = seed.ok_or("No seed")?;
+        Ok(Self { seed, snapshot, inputs })
+    }
+
+    fn start_step(&self) -> u64 {
+        self.snapshot.lines()
+            .find_map(|line| line.strip_prefix("step "))
+            .and_then(|step| step.parse().ok())
+            .unwrap_or(0)
+    }
+
+    fn end_step(&self) -> u64 {
+        self.inputs.last().map_or(self.start_step(), |&(step, _)| step)
+    }
+}
+
+// A recording being played back.
+struct Replay {
+    recording: Recording,
+    // Index of the first input that hasn't been applied yet.
+    next_input: usize,
+    paused: bool,
+    // Where each step's frame is saved, while rendering the replay to frames.
+    frames_dir: Option<PathBuf>,
+    name: String,
+}
+
+// What the left mouse button is doing in edit mode.
+enum Drag {
+    Moving {
+        index: usize,
+        last: Vec2,
+    },
+    // Dragging out the radius from the center.
+    Circle {
+        center: Vec2,
+    },
+    // Dragging from one corner to the opposite one.
+    Rect {
+        start: Vec2,
+    },
+}
+
+struct Model {
+    boids: Vec<Boid>,
+    nodes: Vec<Node>,
+    // The node the arrow keys and delete act on.
+    selected_node: Option<usize>,
+    // Where the mouse grabbed the selected node, relative to its center, while dragging it.
+    node_grab: Option<Vec2>,
+    obstacles: Vec<Obstacle>,
+
+    // In edit mode the mouse places, moves and deletes obstacles instead of adding boids.
+    edit_mode: bool,
+    edit_tool: EditTool,
+    drag: Option<Drag>,
+    // Points placed so far for a new polygon.
+    polygon_points: Vec<Vec2>,
+
+    // Points every boid is drawn to.
+    goals: Vec<Vec2>,
+    // Treat the mouse as a goal too.
+    follow_mouse: bool,
+    // How many prey have been eaten since the last reset.
+    eaten: u32,
+
+    enable_separation: bool,
+    enable_alignment: bool,
+    enable_cohesion: bool,
+    enable_repulsion: bool,
+    enable_avoidance: bool,
+    enable_hunting: bool,
+    enable_goals: bool,
+    // Prey follow behind the first prey boid instead of just flocking.
+    enable_leader: bool,
+
+    prey_rules: SpeciesRules,
+    predator_rules: SpeciesRules,
+
+    // View angles in radians, per neighbor rule. A full turn sees everything.
+    separation_fov: f32,
+    alignment_fov: f32,
+    cohesion_fov: f32,
+    // Which rule's angle [ and ] change.
+    fov_rule: NeighborRule,
+    // The boid whose view cones and neighbors are drawn.
+    debug_boid: Option<usize>,
+
+    boundary_mode: BoundaryMode,
+    // Center of the view in world coordinates. Only moves in open mode.
+    camera: Vec2,
+
+    render_mode: RenderMode,
+
+    // Number of updates since the sketch started.
+    step: u64,
+    // Whether to draw the sparklines.
+    show_stats: bool,
+    // Most recent last.
+    stats_history: VecDeque<FlockStats>,
+    // Open CSV file while recording telemetry.
+    telemetry: Option<(PathBuf, BufWriter<fs::File>)>,
+
+    // Where the boids' randomness comes from, so replays can make the same choices.
+    rng: StdRng,
+    // The last mouse position in window coordinates. Kept here instead of asking the app so
+    // replays can move it.
+    mouse_pos: Vec2,
+    assets: Option<PathBuf>,
+    // Open recording file while recording inputs.
+    recorder: Option<(PathBuf, BufWriter<fs::File>)>,
+    replay: Option<Replay>,
+}
+
+impl Model {
+    fn new(assets: Option<PathBuf>) -> Self {
+        let mut rng = StdRng::seed_from_u64(random());
+        Self {
+            boids: initial_boids(&mut rng),
+            nodes: Vec::new(),
+            selected_node: None,
+            node_grab: None,
+            obstacles: Vec::new(),
+
+            edit_mode: false,
+            edit_tool: EditTool::Circle,
+            drag: None,
+            polygon_points: Vec::new(),
+
+            goals: Vec::new(),
+            follow_mouse: false,
+            eaten: 0,
+
+            enable_separation: true,
+            enable_alignment: true,
+            enable_cohesion: true,
+            enable_repulsion: true,
+            enable_avoidance: true,
+            enable_hunting: true,
+            enable_goals: true,
+            enable_leader: false,
+
+            prey_rules: SpeciesRules::prey(),
+            predator_rules: SpeciesRules::predator(),
+
+            separation_fov: DEFAULT_FOV_DEGREES.to_radians(),
+            alignment_fov: DEFAULT_FOV_DEGREES.to_radians(),
+            cohesion_fov: DEFAULT_FOV_DEGREES.to_radians(),
+            fov_rule: NeighborRule::Separation,
+            debug_boid: None,
+
+            boundary_mode: BoundaryMode::Wrap,
+            camera: Vec2::ZERO,
+
+            render_mode: RenderMode::Neighbors,
+
+            step: 0,
+            show_stats: false,
+            stats_history: VecDeque::with_capacity(STATS_HISTORY),
+            telemetry: None,
+
+            rng,
+            mouse_pos: Vec2::ZERO,
+            assets,
+            recorder: None,
+            replay: None,
+        }
+    }
+
+    fn state_path(&self) -> Option<PathBuf> {
+        self.assets.as_ref().map(|assets| assets.join(STATE_FILE))
+    }
+
+    fn telemetry_dir(&self) -> Option<PathBuf> {
+        self.assets.as_ref().map(|assets| assets.join(TELEMETRY_DIR))
+    }
+
+    fn recording_dir(&self) -> Option<PathBuf> {
+        self.assets.as_ref().map(|assets| assets.join(RECORDING_DIR))
+    }
+
+    // Everything the simulation depends on that isn't in a saved state, then the saved state
+    // itself, one per line.
+    fn snapshot(&self) -> String {
+        let mut lines = vec![
+            format!("step {}", self.step),
+            format!("mouse {} {}", self.mouse_pos.x, self.mouse_pos.y),
+            format!("camera {} {}", self.camera.x, self.camera.y),
+            format!("eaten {}", self.eaten),
+            format!("follow_mouse {}", self.follow_mouse),
+            format!("edit_mode {}", self.edit_mode),
+            format!("edit_tool {:?}", self.edit_tool),
+            // Keys act on these, so a replay has to start with the same ones.
+            format!("fov_rule {}", self.fov_rule.name()),
+            format!("render_mode {}", self.render_mode.name()),
+            format!("show_stats {}", self.show_stats),
+        ];
+        if let Some(index) = self.selected_node {
+            lines.push(format!("selected_node {}", index));
+        }
+        if let Some(index) = self.debug_boid {
+            lines.push(format!("debug_boid {}", index));
+        }
+        lines.extend(self.boids.iter().map(|boid| {
+            let species = match boid.species {
+                Species::Prey => "prey",
+                Species::Predator => "predator",
+            };
+            format!("boid {} {} {} {} {}", species, boid.pos.x, boid.pos.y, boid.vel.x, boid.vel.y)
+        }));
+        lines.extend(self.state_lines());
+        // Saved states round view angles through degrees and keep rects by their centers, so
+        // follow them with the exact values.
+        lines.extend(NEIGHBOR_RULES.iter().map(|&rule| format!("exact_fov {} {}", rule.name(), self.fov(rule))));
+        for (i, obstacle) in self.obstacles.iter().enumerate() {
+            if let Obstacle::Rect(rect) = obstacle {
+                lines.push(format!("exact_rect {} {} {} {} {}", i, rect.left(), rect.bottom(), rect.right(), rect.top()));
+            }
+        }
+        lines.join("\n") + "\n"
+    }
+
+    // Puts the model back the way it was when snapshot was called, and reseeds the randomness.
+    fn restore(&mut self, snapshot: &str, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.boids.clear();
+        self.drag = None;
+        self.polygon_points.clear();
+        self.debug_boid = None;
+
+        // Pull out our own lines and hand the rest to the saved state loader. Some of ours have to
+        // wait until it's done.
+        let mut state = String::new();
+        let mut after_state = Vec::new();
+        for line in snapshot.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let nums: Vec<f32> = words.iter().skip(1).filter_map(|word| word.parse().ok()).collect();
+            match (words.as_slice(), nums.as_slice()) {
+                (&["step", step], _) => self.step = step.parse().unwrap_or(0),
+                (&["mouse", _, _], &[x, y]) => self.mouse_pos = vec2(x, y),
+                (&["camera", _, _], &[x, y]) => self.camera = vec2(x, y),
+                (&["eaten", eaten], _) => self.eaten = eaten.parse().unwrap_or(0),
+                (&["follow_mouse", on], _) => self.follow_mouse = on == "true",
+                (&["edit_mode", on], _) => self.edit_mode = on == "true",
+                (&["edit_tool", name], _) => self.edit_tool = EditTool::from_name(name).unwrap_or(EditTool::Circle),
+                (&["fov_rule", name], _) => self.fov_rule = NeighborRule::from_name(name).unwrap_or(NeighborRule::Separation),
+                (&["render_mode", name], _) => self.render_mode = RenderMode::from_name(name).unwrap_or(RenderMode::Neighbors),
+                (&["show_stats", on], _) => self.show_stats = on == "true",
+                (&["debug_boid", index], _) => self.debug_boid = index.parse().ok(),
+                (&["boid", species, ..], &[x, y, vx, vy]) => {
+                    let species = if species == "predator" { Species::Predator } else { Species::Prey };
+                    let mut boid = Boid::new(x, y, species, &mut self.rng);
+                    boid.vel = vec2(vx, vy);
+                    self.boids.push(boid);
+                }
+                (&["selected_node", ..] | &["exact_fov", ..] | &["exact_rect", ..], _) => after_state.push(line),
+                _ => {
+                    state += line;
+                    state += "\n";
+                }
+            }
+        }
+        // Making boids used up some randomness, so start over.
+        self.rng = StdRng::seed_from_u64(seed);
+
+        self.load_state(&state);
+        for line in after_state {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match *words.as_slice() {
+                ["selected_node", index] => self.selected_node = index.parse().ok(),
+                ["exact_fov", name, fov] => {
+                    if let (Some(rule), Ok(fov)) = (NeighborRule::from_name(name), fov.parse()) {
+                        *self.fov_mut(rule) = fov;
+                    }
+                }
+                ["exact_rect", index, left, bottom, right, top] => {
+                    let nums: Vec<f32> = [left, bottom, right, top].iter().filter_map(|num| num.parse().ok()).collect();
+                    let obstacle = index.parse().ok().and_then(|index: usize| self.obstacles.get_mut(index));
+                    if let (Some(Obstacle::Rect(rect)), &[left, bottom, right, top]) = (obstacle, nums.as_slice()) {
+                        *rect = Rect::from_corners(pt2(left, bottom), pt2(right, top));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Starts writing every input to a new recording, starting from the model as it is now, or
+    // finishes the current recording.
+    fn toggle_recording(&mut self) {
+        if let Some((path, mut writer)) = self.recorder.take() {
+            match writer.flush() {
+                Ok(()) => println!("Stopped recording to {}", path.display()),
+                Err(e) => println!("Failed to write {}: {}", path.display(), e),
+            }
+            return;
+        }
+        if self.replay.is_some() {
+            println!("Can't record during a replay");
+            return;
+        }
+        let dir = match self.recording_dir() {
+            Some(dir) => dir,
+            None => {
+                println!("No assets folder to record to");
+                return;
+            }
+        };
+
+        // Anything half done with the mouse would be missing its start from the recording.
+        self.drag = None;
+        self.node_grab = None;
+        self.polygon_points.clear();
+
+        // Reseed so the recording knows where the randomness starts from.
+        let seed = random();
+        self.rng = StdRng::seed_from_u64(seed);
+
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let path = dir.join(format!("run-{}.txt", secs));
+        let snapshot = self.snapshot();
+        let result = fs::create_dir_all(&dir)
+            .and_then(|_| fs::File::create(&path))
+            .and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                writeln!(writer, "seed {}", seed)?;
+                write!(writer, "{}", snapshot)?;
+                Ok(writer)
+            });
+        match result {
+            Ok(writer) => {
+                println!("Recording to {}", path.display());
+                self.recorder = Some((path, writer));
+            }
+            Err(e) => println!("Failed to create {}: {}", path.display(), e),
+        }
+    }
+
+    fn record(&mut self, input: Input) {
+        let step = self.step;
+        let failed = match &mut self.recorder {
+            Some((path, writer)) => match writeln!(writer, "input {} {}", step, input.to_line()) {
+                Ok(()) => false,
+                Err(e) => {
+                    println!("Failed to write {}: {}", path.display(), e);
+                    true
+                }
+            },
+            None => false,
+        };
+        if failed {
+            self.recorder = None;
+        }
+    }
+
+    // Starts replaying the latest recording, or goes back to the live simulation.
+    fn toggle_replay(&mut self) {
+        if self.replay.take().is_some() {
+            println!("Stopped replay");
+            return;
+        }
+        if self.recorder.is_some() {
+            println!("Stop recording before replaying");
+            return;
+        }
+
+        // Recordings are named after when they started, so the last one by name is the latest.
+        let latest = self.recording_dir()
+            .and_then(|dir| fs::read_dir(dir).ok())
+            .and_then(|entries| entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).max());
+        let path = match latest {
+            Some(path) => path,
+            None => {
+                println!("No recordings to replay");
+                return;
+            }
+        };
+        match Recording::load(&path) {
+            Ok(recording) => {
+                let name = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+                println!(
+                    "Replaying {} from step {} (last input at step {})",
+                    path.display(), recording.start_step(), recording.end_step(),
+                );
+                self.restore(&recording.snapshot, recording.seed);
+                self.replay = Some(Replay {
+                    recording,
+                    next_input: 0,
+                    paused: false,
+                    frames_dir: None,
+                    name,
+                });
+            }
+            Err(e) => println!("Failed to load {}: {}", path.display(), e),
+        }
+    }
+
+    // Runs the replay to the given step, starting over if it's already past it.
+    fn seek(&mut self, target: u64) {
+        let mut replay = match self.replay.take() {
+            Some(replay) => replay,
+            None => return,
+        };
+        let target = target.max(replay.recording.start_step());
+        if target < self.step {
+            self.restore(&replay.recording.snapshot, replay.recording.seed);
+            replay.next_input = 0;
+        }
+        while self.step < target {
+            replay_step(self, &mut replay);
+        }
+        self.replay = Some(replay);
+        println!("Replay at step {}", self.step);
+    }
+
+    fn stats(&self) -> FlockStats {
+        let prey: Vec<usize> = (0..self.boids.len())
+            .filter(|&i| self.boids[i].species == Species::Prey)
+            .collect();
+
+        let mut heading_sum = Vec2::ZERO;
+        let mut speed_sum = 0.0;
+        let mut nearest_sum = 0.0;
+        let mut nearest_count = 0;
+        let mut neighbor_counts = [0; NEIGHBOR_BUCKETS];
+        for &i in &prey {
+            let boid = &self.boids[i];
+            heading_sum += boid.vel.normalize_or_zero();
+            speed_sum += boid.vel.length();
+
+            let mut nearest = f32::INFINITY;
+            let mut neighbors = 0;
+            for &j in &prey {
+                if i == j {
+                    continue;
+                }
+                let dist = self.boundary_mode.offset(boid.pos, self.boids[j].pos).length();
+                nearest = nearest.min(dist);
+                if dist < NEIGHBOR_DIST {
+                    neighbors += 1;
+                }
+            }
+            if nearest.is_finite() {
+                nearest_sum += nearest;
+                nearest_count += 1;
+            }
+            neighbor_counts[neighbors.min(NEIGHBOR_BUCKETS - 1)] += 1;
+        }
+
+        let clusters = {
+            let labels = self.clusters();
+            let mut prey_labels: Vec<usize> = prey.iter().map(|&i| labels[i]).collect();
+            prey_labels.sort_unstable();
+            prey_labels.dedup();
+            prey_labels.len()
+        };
+
+        let n = prey.len().max(1) as f32;
+        FlockStats {
+            polarization: heading_sum.length() / n,
+            mean_nearest_dist: if nearest_count > 0 { nearest_sum / nearest_count as f32 } else { 0.0 },
+            clusters,
+            avg_speed: speed_sum / n,
+            neighbor_counts,
+        }
+    }
+
+    // Starts writing a line of statistics per step to a new CSV file, or finishes the current one.
+    fn toggle_telemetry(&mut self, dir: Option<PathBuf>) {
+        if let Some((path, mut writer)) = self.telemetry.take() {
+            match writer.flush() {
+                Ok(()) => println!("Stopped recording telemetry to {}", path.display()),
+                Err(e) => println!("Failed to write {}: {}", path.display(), e),
+            }
+            return;
+        }
+
+        let dir = match dir {
+            Some(dir) => dir,
+            None => {
+                println!("No assets folder to record telemetry to");
+                return;
+            }
+        };
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let path = dir.join(format!("stats-{}.csv", secs));
+        let result = fs::create_dir_all(&dir)
+            .and_then(|_| fs::File::create(&path))
+            .and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                writeln!(writer, "{}", FlockStats::csv_header())?;
+                Ok(writer)
+            });
+        match result {
+            Ok(writer) => {
+                println!("Recording telemetry to {}", path.display());
+                self.telemetry = Some((path, writer));
+            }
+            Err(e) => println!("Failed to create {}: {}", path.display(), e),
+        }
+    }
+
+    // Measures this step's flock, if anything wants the numbers.
+    fn record_stats(&mut self) {
+        if !self.show_stats && self.telemetry.is_none() {
+            return;
+        }
+        let stats = self.stats();
+
+        let step = self.step;
+        let failed = match &mut self.telemetry {
+            Some((path, writer)) => match writeln!(writer, "{}", stats.csv_line(step)) {
+                Ok(()) => false,
+                Err(e) => {
+                    println!("Failed to write {}: {}", path.display(), e);
+                    true
+                }
+            },
+            None => false,
+        };
+        if failed {
+            self.telemetry = None;
+        }
+
+        if self.stats_history.len() == STATS_HISTORY {
+            self.stats_history.pop_front();
+        }
+        self.stats_history.push_back(stats);
+    }
+
+    // Labels each boid with the flock it belongs to. Prey within NEIGHBOR_DIST of each other are in
+    // the same flock, as are any prey linked through a chain of such neighbors. Predators are
+    // always alone. Labels are numbered from 0 in order of first appearance.
+    fn clusters(&self) -> Vec<usize> {
+        let mut parents: Vec<usize> = (0..self.boids.len()).collect();
+        for (i, boid) in self.boids.iter().enumerate() {
+            if boid.species != Species::Prey {
+                continue;
+            }
+            for (j, other) in self.boids.iter().enumerate().skip(i + 1) {
+                let close = other.species == Species::Prey
+                    && self.boundary_mode.offset(boid.pos, other.pos).length() < NEIGHBOR_DIST;
+                if close {
+                    let (a, b) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                    parents[a] = b;
+                }
+            }
+        }
 
-const WIDTH: f32 = 800.0;
-const HEIGHT: f32 = 600.0;
+        let mut labels = Vec::with_capacity(self.boids.len());
+        let mut root_labels = std::collections::HashMap::new();
+        for i in 0..self.boids.len() {
+            let root = find_root(&mut parents, i);
+            let next_label = root_labels.len();
+            labels.push(*root_labels.entry(root).or_insert(next_label));
+        }
+        labels
+    }
 
-const INITIAL_BOID_COUNT: u32 = 150;
-const BOID_RADIUS: f32 = 4.0;
-const MAX_SPEED: f32 = 2.0;
-const MAX_FORCE: f32 = 0.03;
+    // The mouse position in world coordinates.
+    fn mouse(&self) -> Vec2 {
+        self.mouse_pos + self.camera
+    }
 
-const TWO_PI: f32 = 2.0 * std::f32::consts::PI;
+    fn fov_mut(&mut self, rule: NeighborRule) -> &mut f32 {
+        match rule {
+            NeighborRule::Separation => &mut self.separation_fov,
+            NeighborRule::Alignment => &mut self.alignment_fov,
+            NeighborRule::Cohesion => &mut self.cohesion_fov,
+        }
+    }
 
-struct Boid {
-    pos: Vec2,
-    vel: Vec2,
-}
+    fn fov(&self, rule: NeighborRule) -> f32 {
+        match rule {
+            NeighborRule::Separation => self.separation_fov,
+            NeighborRule::Alignment => self.alignment_fov,
+            NeighborRule::Cohesion => self.cohesion_fov,
+        }
+    }
 
-impl Boid {
-    fn new(x: f32, y: f32) -> Self {
-        let angle = random_range(0.0, TWO_PI);
-        Self {
-            pos: vec2(x, y),
-            vel: vec2(angle.cos(), angle.sin()),
+    // Widens or narrows the view angle of the rule picked with 1, 2 and 3.
+    fn adjust_fov(&mut self, degrees: f32) {
+        let rule = self.fov_rule;
+        let fov = self.fov_mut(rule);
+        *fov = clamp(*fov + degrees.to_radians(), 0.0, TWO_PI);
+        println!("{:?} view angle: {:.0} degrees", rule, fov.to_degrees());
+    }
+
+    // Indices of the boids that boid i takes into account for a rule.
+    fn visible_neighbors(&self, i: usize, rule: NeighborRule) -> Vec<usize> {
+        let boid = &self.boids[i];
+        self.boids.iter()
+            .enumerate()
+            .filter(|&(j, other)| {
+                let offset = self.boundary_mode.offset(boid.pos, other.pos);
+                let dist = offset.length();
+                j != i
+                    && other.species == boid.species
+                    && dist > 0.0
+                    && dist < rule.radius()
+                    && can_see(boid, offset, self.fov(rule))
+            })
+            .map(|(j, _)| j)
+            .collect()
+    }
+
+    fn rules(&self, species: Species) -> &SpeciesRules {
+        match species {
+            Species::Prey => &self.prey_rules,
+            Species::Predator => &self.predator_rules,
         }
     }
-}
 
-struct Repel {
-    pos: Vec2,
-}
+    // The prey everyone else follows when leader following is on.
+    fn leader(&self) -> Option<usize> {
+        if !self.enable_leader {
+            return None;
+        }
+        self.boids.iter().position(|boid| boid.species == Species::Prey)
+    }
 
-impl Repel {
-    fn new(x: f32, y: f32) -> Self {
-        Self {
-            pos: vec2(x, y),
+    fn count(&self, species: Species) -> usize {
+        self.boids.iter().filter(|boid| boid.species == species).count()
+    }
+
+    // The topmost node near p, if any.
+    fn pick_node(&self, p: Vec2) -> Option<usize> {
+        self.nodes.iter().rposition(|node| node.pos.distance(p) < NODE_PICK_DIST)
+    }
+
+    // Changes the selected node's radius and strength, keeping both positive.
+    fn adjust_node(&mut self, radius: f32, strength: f32) {
+        if let Some(node) = self.selected_node.and_then(|i| self.nodes.get_mut(i)) {
+            node.radius = (node.radius + radius).max(NODE_RADIUS_STEP);
+            node.strength = (node.strength + strength).max(NODE_STRENGTH_STEP);
+            println!(
+                "{:?} node: radius {}, strength {}, {:?} falloff",
+                node.kind, node.radius, node.strength, node.falloff,
+            );
         }
     }
-}
 
-struct Model {
-    boids: Vec<Boid>,
-    repels: Vec<Repel>,
+    // The topmost obstacle at p, if any.
+    fn pick_obstacle(&self, p: Vec2) -> Option<usize> {
+        self.obstacles.iter()
+            .rposition(|obstacle| obstacle.contains(p) || obstacle.closest_edge_point(p).distance(p) < PICK_DIST)
+    }
 
-    enable_separation: bool,
-    enable_alignment: bool,
-    enable_cohesion: bool,
-    enable_repulsion: bool,
+    // The enable flag and the prey and predator factors of a rule, by name.
+    fn rule_mut(&mut self, name: &str) -> Option<(&mut bool, &mut f32, &mut f32)> {
+        let (prey, predator) = (&mut self.prey_rules, &mut self.predator_rules);
+        match name {
+            "separation" => Some((&mut self.enable_separation, &mut prey.separation_factor, &mut predator.separation_factor)),
+            "alignment" => Some((&mut self.enable_alignment, &mut prey.alignment_factor, &mut predator.alignment_factor)),
+            "cohesion" => Some((&mut self.enable_cohesion, &mut prey.cohesion_factor, &mut predator.cohesion_factor)),
+            "repulsion" => Some((&mut self.enable_repulsion, &mut prey.repulsion_factor, &mut predator.repulsion_factor)),
+            "avoidance" => Some((&mut self.enable_avoidance, &mut prey.avoidance_factor, &mut predator.avoidance_factor)),
+            "hunting" => Some((&mut self.enable_hunting, &mut prey.hunting_factor, &mut predator.hunting_factor)),
+            "goals" => Some((&mut self.enable_goals, &mut prey.goal_factor, &mut predator.goal_factor)),
+            "leader" => Some((&mut self.enable_leader, &mut prey.leader_factor, &mut predator.leader_factor)),
+            _ => None,
+        }
+    }
 
-    separation_factor: f32,
-    alignment_factor: f32,
-    cohesion_factor: f32,
-    repulsion_factor: f32,
-}
+    // Writes the rule settings, force nodes, goals and obstacles, one per line.
+    fn save(&self, path: &Path) {
+        let lines = self.state_lines();
+        let result = path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, lines.join("\n") + "\n"));
+        match result {
+            Ok(()) => println!("Saved to {}", path.display()),
+            Err(e) => println!("Failed to save {}: {}", path.display(), e),
+        }
+    }
 
-impl Model {
-    fn new() -> Self {
-        Self {
-            boids: (0..INITIAL_BOID_COUNT).map(|_| Boid::new(0.0, 0.0)).collect(),
-            repels: Vec::new(),
+    fn state_lines(&self) -> Vec<String> {
+        let (prey, predator) = (&self.prey_rules, &self.predator_rules);
+        let mut lines = vec![
+            format!("separation {} {} {}", self.enable_separation, prey.separation_factor, predator.separation_factor),
+            format!("alignment {} {} {}", self.enable_alignment, prey.alignment_factor, predator.alignment_factor),
+            format!("cohesion {} {} {}", self.enable_cohesion, prey.cohesion_factor, predator.cohesion_factor),
+            format!("repulsion {} {} {}", self.enable_repulsion, prey.repulsion_factor, predator.repulsion_factor),
+            format!("avoidance {} {} {}", self.enable_avoidance, prey.avoidance_factor, predator.avoidance_factor),
+            format!("hunting {} {} {}", self.enable_hunting, prey.hunting_factor, predator.hunting_factor),
+            format!("goals {} {} {}", self.enable_goals, prey.goal_factor, predator.goal_factor),
+            format!("leader {} {} {}", self.enable_leader, prey.leader_factor, predator.leader_factor),
+        ];
+        lines.push(format!("boundary {}", self.boundary_mode.name()));
+        lines.extend(NEIGHBOR_RULES.iter().map(|&rule| format!("fov {} {}", rule.name(), self.fov(rule).to_degrees())));
+        lines.extend(self.nodes.iter().map(Node::to_line));
+        lines.extend(self.goals.iter().map(|goal| format!("goal {} {}", goal.x, goal.y)));
+        lines.extend(self.obstacles.iter().map(Obstacle::to_line));
+        lines
+    }
 
-            enable_separation: true,
-            enable_alignment: true,
-            enable_cohesion: true,
-            enable_repulsion: true,
+    // Reads back a file written by save. Boids aren't saved, so they're left alone.
+    fn load(&mut self, path: &Path) {
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                self.load_state(&contents);
+                println!("Loaded {}", path.display());
+            }
+            Err(e) => println!("Failed to read {}: {}", path.display(), e),
+        }
+    }
 
-            separation_factor: 1.5,
-            alignment_factor: 1.0,
-            cohesion_factor: 1.0,
-            repulsion_factor: 1.5,
+    fn load_state(&mut self, contents: &str) {
+        self.nodes.clear();
+        self.selected_node = None;
+        self.node_grab = None;
+        self.goals.clear();
+        self.obstacles.clear();
+        for line in contents.lines() {
+            let mut words = line.split_whitespace();
+            let kind = match words.next() {
+                Some(kind) => kind,
+                None => continue,
+            };
+            let args: Vec<&str> = words.collect();
+            let nums: Vec<f32> = args.iter().filter_map(|arg| arg.parse().ok()).collect();
+            if let Some((enable, prey_factor, predator_factor)) = self.rule_mut(kind) {
+                let enabled = args.first().and_then(|arg| arg.parse().ok());
+                match (enabled, nums.as_slice()) {
+                    (Some(enabled), &[prey, predator]) => {
+                        *enable = enabled;
+                        *prey_factor = prey;
+                        *predator_factor = predator;
+                    }
+                    _ => println!("Skipping bad line: {}", line),
+                }
+                continue;
+            }
+            if kind == "boundary" {
+                match args.first().and_then(|name| BoundaryMode::from_name(name)) {
+                    Some(mode) => self.boundary_mode = mode,
+                    None => println!("Skipping bad line: {}", line),
+                }
+                continue;
+            }
+            if kind == "fov" {
+                match (args.first().and_then(|name| NeighborRule::from_name(name)), nums.as_slice()) {
+                    (Some(rule), &[degrees]) => *self.fov_mut(rule) = degrees.to_radians(),
+                    _ => println!("Skipping bad line: {}", line),
+                }
+                continue;
+            }
+            if kind == "node" {
+                let node_kind = args.first().and_then(|name| NodeKind::from_name(name));
+                let falloff = args.get(1).and_then(|name| Falloff::from_name(name));
+                match (node_kind, falloff, nums.as_slice()) {
+                    (Some(node_kind), Some(falloff), &[x, y, radius, strength]) => {
+                        self.nodes.push(Node {
+                            falloff,
+                            radius,
+                            strength,
+                            ..Node::new(x, y, node_kind)
+                        });
+                    }
+                    _ => println!("Skipping bad line: {}", line),
+                }
+                continue;
+            }
+            match (kind, nums.as_slice()) {
+                ("goal", &[x, y]) => self.goals.push(vec2(x, y)),
+                ("circle", &[x, y, radius]) => self.obstacles.push(Obstacle::Circle {
+                    center: vec2(x, y),
+                    radius,
+                }),
+                ("rect", &[x, y, w, h]) => self.obstacles.push(Obstacle::Rect(Rect::from_x_y_w_h(x, y, w, h))),
+                ("polygon", nums) if nums.len() >= 6 && nums.len() % 2 == 0 => {
+                    let points = nums.chunks(2).map(|xy| vec2(xy[0], xy[1])).collect();
+                    self.obstacles.push(Obstacle::Polygon(points));
+                }
+                _ => println!("Skipping bad line: {}", line),
+            }
+        }
+    }
+}
+
+// Applies the inputs recorded for this step, then steps the simulation like it did live.
+fn replay_step(model: &mut Model, replay: &mut Replay) {
+    while let Some(&(step, input)) = replay.recording.inputs.get(replay.next_input) {
+        if step > model.step {
+            break;
         }
+        apply_input(model, input);
+        replay.next_input += 1;
+    }
+    simulate(model);
+}
+
+// Draws values as a line in rect, scaled so max reaches the top. Values are oldest first.
+fn draw_sparkline(draw: &Draw, rect: Rect, values: &[f32], max: f32, label: &str, color: Rgba) {
+    draw.rect()
+        .xy(rect.xy())
+        .wh(rect.wh())
+        .color(Rgba::new(0.0, 0.0, 0.0, 0.4));
+    if values.len() > 1 && max > 0.0 {
+        let dx = rect.w() / (STATS_HISTORY - 1) as f32;
+        let points = values.iter().enumerate().map(|(i, &value)| {
+            let x = rect.right() - (values.len() - 1 - i) as f32 * dx;
+            let y = rect.bottom() + (value / max).min(1.0) * rect.h();
+            pt2(x, y)
+        });
+        draw.polyline()
+            .weight(1.5)
+            .color(color)
+            .points(points);
     }
+    let latest = values.last().copied().unwrap_or(0.0);
+    draw.text(&format!("{} {:.2}", label, latest))
+        .left_justify()
+        .color(WHITE)
+        .font_size(11)
+        .xy(rect.xy())
+        .wh(rect.wh() - vec2(8.0, 0.0));
 }
 
 fn model(app: &App) -> Model {
@@ -87,50 +1551,324 @@ fn model(app: &App) -> Model {
         .build()
         .unwrap();
 
-    Model::new()
+    Model::new(app.assets_path().ok())
 }
 
-fn event(app: &App, model: &mut Model, event: WindowEvent) {
+fn event(_app: &App, model: &mut Model, event: WindowEvent) {
     match event {
-        WindowEvent::MousePressed(MouseButton::Left) => {
-            let pos = app.mouse.position();
-            model.boids.push(Boid::new(pos.x, pos.y));
+        WindowEvent::KeyPressed(Key::F5) => model.toggle_recording(),
+        WindowEvent::KeyPressed(Key::F6) => model.toggle_replay(),
+        // Saving and telemetry don't change the simulation, so they're left out of recordings and
+        // still work during replays.
+        WindowEvent::KeyPressed(Key::S) => {
+            if let Some(path) = model.state_path() {
+                model.save(&path);
+            }
+        }
+        WindowEvent::KeyPressed(Key::L) => {
+            if model.recorder.is_some() || model.replay.is_some() {
+                println!("Can't load while recording or replaying");
+            } else if let Some(path) = model.state_path() {
+                model.load(&path);
+            }
+        }
+        WindowEvent::KeyPressed(Key::X) => {
+            let dir = model.telemetry_dir();
+            model.toggle_telemetry(dir);
+        }
+        WindowEvent::KeyPressed(key) if model.replay.is_some() => replay_key(model, key),
+        // Live input doesn't reach the simulation during a replay.
+        _ if model.replay.is_some() => {}
+        _ => {
+            if let Some(input) = Input::from_event(&event) {
+                model.record(input);
+                apply_input(model, input);
+            }
+        }
+    }
+}
+
+// Replay controls: Space pauses, comma and period step back and forth, Page Up and Page Down
+// seek further, Home and End jump to the start and the last input, and F7 renders to frames.
+fn replay_key(model: &mut Model, key: Key) {
+    let (step, start, end) = match &model.replay {
+        Some(replay) => (model.step, replay.recording.start_step(), replay.recording.end_step()),
+        None => return,
+    };
+    match key {
+        Key::Space => {
+            if let Some(replay) = &mut model.replay {
+                replay.paused = !replay.paused;
+                println!("Replay paused: {} (step {})", replay.paused, step);
+            }
+        }
+        Key::Comma => model.seek(step.saturating_sub(1)),
+        Key::Period => model.seek(step + 1),
+        Key::PageUp => model.seek(step.saturating_sub(SEEK_STEPS)),
+        Key::PageDown => model.seek(step + SEEK_STEPS),
+        Key::Home => model.seek(start),
+        Key::End => model.seek(end),
+        Key::F7 => {
+            let frames_dir = model.assets.as_ref().map(|assets| assets.join(FRAMES_DIR));
+            if let Some(replay) = &mut model.replay {
+                replay.frames_dir = match (&replay.frames_dir, frames_dir) {
+                    (None, Some(dir)) => {
+                        let dir = dir.join(&replay.name);
+                        match fs::create_dir_all(&dir) {
+                            Ok(()) => {
+                                println!("Rendering replay frames to {}", dir.display());
+                                Some(dir)
+                            }
+                            Err(e) => {
+                                println!("Failed to create {}: {}", dir.display(), e);
+                                None
+                            }
+                        }
+                    }
+                    _ => {
+                        println!("Stopped rendering replay frames");
+                        None
+                    }
+                };
+            }
+        }
+        _ => {}
+    }
+}
+
+// Does whatever an input does to the model, whether it's happening live or being replayed.
+fn apply_input(model: &mut Model, input: Input) {
+    if let Input::MouseMoved(pos) = input {
+        model.mouse_pos = pos;
+    }
+    let pos = model.mouse();
+    match input {
+        Input::KeyPressed(Key::E) => {
+            model.edit_mode = !model.edit_mode;
+            model.drag = None;
+            model.polygon_points.clear();
+            println!("Edit mode: {} (tool: {:?})", model.edit_mode, model.edit_tool);
+        }
+        Input::KeyPressed(Key::T) if model.edit_mode => {
+            model.edit_tool = model.edit_tool.next();
+            model.polygon_points.clear();
+            println!("Tool: {:?}", model.edit_tool);
+        }
+        Input::KeyPressed(Key::Return) if model.polygon_points.len() >= 3 => {
+            let points = std::mem::take(&mut model.polygon_points);
+            model.obstacles.push(Obstacle::Polygon(points));
+        }
+        Input::KeyPressed(Key::Back) => {
+            // Escape would quit, so cancel polygons with backspace.
+            model.polygon_points.clear();
+        }
+        Input::KeyPressed(Key::A) => {
+            model.enable_avoidance = !model.enable_avoidance;
+            println!("Obstacle avoidance: {}", model.enable_avoidance);
+        }
+        Input::MousePressed(MouseButton::Left) if model.edit_mode => {
+            // Grab an existing obstacle, unless we're in the middle of a polygon.
+            model.drag = match model.pick_obstacle(pos) {
+                Some(index) if model.polygon_points.is_empty() => Some(Drag::Moving { index, last: pos }),
+                _ => match model.edit_tool {
+                    EditTool::Circle => Some(Drag::Circle { center: pos }),
+                    EditTool::Rect => Some(Drag::Rect { start: pos }),
+                    EditTool::Polygon => {
+                        model.polygon_points.push(pos);
+                        None
+                    }
+                },
+            };
+        }
+        Input::MouseReleased(MouseButton::Left) if model.edit_mode => {
+            match model.drag.take() {
+                Some(Drag::Circle { center }) if center.distance(pos) > 1.0 => {
+                    model.obstacles.push(Obstacle::Circle {
+                        center,
+                        radius: center.distance(pos),
+                    });
+                }
+                Some(Drag::Rect { start }) if (start - pos).abs().min_element() > 1.0 => {
+                    model.obstacles.push(Obstacle::Rect(Rect::from_corners(start, pos)));
+                }
+                _ => {}
+            }
+        }
+        Input::MousePressed(MouseButton::Right) if model.edit_mode => {
+            if let Some(index) = model.pick_obstacle(pos) {
+                model.obstacles.remove(index);
+            }
+        }
+        Input::MousePressed(MouseButton::Left) => {
+            model.boids.push(Boid::new(pos.x, pos.y, Species::Prey, &mut model.rng));
+        }
+        Input::MousePressed(MouseButton::Right) => {
+            // Grab the node under the mouse, or drop a new repel node there.
+            let index = match model.pick_node(pos) {
+                Some(index) => index,
+                None => {
+                    model.nodes.push(Node::new(pos.x, pos.y, NodeKind::Repel));
+                    model.nodes.len() - 1
+                }
+            };
+            model.selected_node = Some(index);
+            model.node_grab = Some(pos - model.nodes[index].pos);
+        }
+        Input::MouseReleased(MouseButton::Right) => {
+            model.node_grab = None;
+        }
+        Input::KeyPressed(Key::N) => {
+            model.nodes.push(Node::new(pos.x, pos.y, NodeKind::Attract));
+            model.selected_node = Some(model.nodes.len() - 1);
+        }
+        Input::KeyPressed(Key::Delete) => {
+            if let Some(index) = model.selected_node.take() {
+                model.nodes.remove(index);
+                model.node_grab = None;
+            }
+        }
+        Input::KeyPressed(Key::Up) => model.adjust_node(NODE_RADIUS_STEP, 0.0),
+        Input::KeyPressed(Key::Down) => model.adjust_node(-NODE_RADIUS_STEP, 0.0),
+        Input::KeyPressed(Key::Right) => model.adjust_node(0.0, NODE_STRENGTH_STEP),
+        Input::KeyPressed(Key::Left) => model.adjust_node(0.0, -NODE_STRENGTH_STEP),
+        Input::KeyPressed(Key::Q) => {
+            if let Some(node) = model.selected_node.and_then(|i| model.nodes.get_mut(i)) {
+                node.falloff = node.falloff.next();
+            }
+            model.adjust_node(0.0, 0.0);
+        }
+        Input::KeyPressed(Key::K) => {
+            if let Some(node) = model.selected_node.and_then(|i| model.nodes.get_mut(i)) {
+                node.kind = match node.kind {
+                    NodeKind::Repel => NodeKind::Attract,
+                    NodeKind::Attract => NodeKind::Repel,
+                };
+            }
+            model.adjust_node(0.0, 0.0);
+        }
+        Input::KeyPressed(Key::P) => {
+            model.boids.push(Boid::new(pos.x, pos.y, Species::Predator, &mut model.rng));
+        }
+        Input::KeyPressed(Key::G) => {
+            model.goals.push(pos);
+        }
+        Input::KeyPressed(Key::M) => {
+            model.follow_mouse = !model.follow_mouse;
+            println!("Follow mouse: {}", model.follow_mouse);
+        }
+        Input::KeyPressed(Key::H) => {
+            model.enable_hunting = !model.enable_hunting;
+            println!("Hunting: {}", model.enable_hunting);
+        }
+        Input::KeyPressed(Key::F) => {
+            model.enable_leader = !model.enable_leader;
+            println!("Leader following: {}", model.enable_leader);
+        }
+        Input::KeyPressed(Key::Key1) => {
+            model.fov_rule = NeighborRule::Separation;
+            println!("Adjusting view angle for: {:?}", model.fov_rule);
+        }
+        Input::KeyPressed(Key::Key2) => {
+            model.fov_rule = NeighborRule::Alignment;
+            println!("Adjusting view angle for: {:?}", model.fov_rule);
+        }
+        Input::KeyPressed(Key::Key3) => {
+            model.fov_rule = NeighborRule::Cohesion;
+            println!("Adjusting view angle for: {:?}", model.fov_rule);
         }
-        WindowEvent::MousePressed(MouseButton::Right) => {
-            let pos = app.mouse.position();
-            model.repels.push(Repel::new(pos.x, pos.y));
+        Input::KeyPressed(Key::LBracket) => {
+            model.adjust_fov(-FOV_STEP_DEGREES);
         }
-        WindowEvent::KeyPressed(Key::R) => {
-            model.boids = (0..INITIAL_BOID_COUNT).map(|_| Boid::new(0.0, 0.0)).collect();
-            model.repels = Vec::new();
+        Input::KeyPressed(Key::RBracket) => {
+            model.adjust_fov(FOV_STEP_DEGREES);
+        }
+        Input::KeyPressed(Key::C) => {
+            model.render_mode = model.render_mode.next();
+            println!("Render mode: {:?}", model.render_mode);
+        }
+        Input::KeyPressed(Key::I) => {
+            model.show_stats = !model.show_stats;
+            println!("Show stats: {}", model.show_stats);
+        }
+        Input::KeyPressed(Key::B) => {
+            model.boundary_mode = model.boundary_mode.next();
+            if model.boundary_mode != BoundaryMode::Open {
+                model.camera = Vec2::ZERO;
+            }
+            println!("Boundary mode: {:?}", model.boundary_mode);
+        }
+        Input::KeyPressed(Key::V) => {
+            // Pick the boid closest to the mouse, or stop debugging.
+            model.debug_boid = match model.debug_boid {
+                Some(_) => None,
+                None => model.boids.iter()
+                    .enumerate()
+                    .min_by(|(_, a), (_, b)| a.pos.distance(pos).partial_cmp(&b.pos.distance(pos)).unwrap())
+                    .map(|(i, _)| i),
+            };
+        }
+        Input::KeyPressed(Key::R) => {
+            model.debug_boid = None;
+            model.camera = Vec2::ZERO;
+            model.boids = initial_boids(&mut model.rng);
+            model.nodes = Vec::new();
+            model.selected_node = None;
+            model.node_grab = None;
+            model.goals = Vec::new();
+            model.obstacles = Vec::new();
+            model.eaten = 0;
         }
         _ => {}
     }
 }
 
 fn update(_app: &App, model: &mut Model, _update: Update) {
+    match model.replay.take() {
+        Some(mut replay) => {
+            if !replay.paused {
+                replay_step(model, &mut replay);
+            }
+            model.replay = Some(replay);
+        }
+        None => simulate(model),
+    }
+}
+
+// Moves the simulation forward one step.
+fn simulate(model: &mut Model) {
+    let mouse = model.mouse();
+    if let Some(Drag::Moving { index, last }) = &mut model.drag {
+        let pos = mouse;
+        // The obstacle might have been deleted mid drag.
+        if let Some(obstacle) = model.obstacles.get_mut(*index) {
+            obstacle.translate(pos - *last);
+        }
+        *last = pos;
+    }
+    if let (Some(index), Some(grab)) = (model.selected_node, model.node_grab) {
+        if let Some(node) = model.nodes.get_mut(index) {
+            node.pos = mouse - grab;
+        }
+    }
+
     // Collect forces and apply them after computing them.
     let mut accels = vec![Vec2::ZERO; model.boids.len()];
 
-    if model.enable_separation && model.separation_factor > 0.0 {
-        for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
+    if model.enable_separation {
+        for (i, (boid, accel)) in model.boids.iter().zip(accels.iter_mut()).enumerate() {
+            let rules = model.rules(boid.species);
             let separation = {
                 // Try to steer away from nearby boids.
-                let desired_separation = 25.0;
-
                 let mut steer = Vec2::ZERO;
                 let mut count = 0;
 
-                // Check if we're too close to all other boids.
-                for other in &model.boids {
-                    let dist = boid.pos.distance(other.pos);
-
-                    // If we're too close, modify our steering vector.
-                    if dist > 0.0 && dist < desired_separation {
-                        let diff = (boid.pos - other.pos).normalize() / dist;
-                        steer += diff;
-                        count += 1;
-                    }
+                // Modify our steering vector for every boid of our kind we're too close to and
+                // can see.
+                for j in model.visible_neighbors(i, NeighborRule::Separation) {
+                    let offset = model.boundary_mode.offset(boid.pos, model.boids[j].pos);
+                    let diff = -offset.normalize() / offset.length();
+                    steer += diff;
+                    count += 1;
                 }
 
                 // Average out the steering.
@@ -139,168 +1877,641 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
                 }
 
                 if steer != Vec2::ZERO {
-                    steer = steer.clamp_length(MAX_SPEED, MAX_SPEED);
+                    steer = steer.clamp_length(rules.max_speed, rules.max_speed);
                     steer -= boid.vel;
                     steer = steer.clamp_length_max(MAX_FORCE);
                 }
 
                 steer
             };
-            *accel += separation * model.separation_factor;
+            *accel += separation * rules.separation_factor;
         }
     }
 
-    if model.enable_alignment && model.alignment_factor > 0.0 {
-        for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
+    if model.enable_alignment {
+        for (i, (boid, accel)) in model.boids.iter().zip(accels.iter_mut()).enumerate() {
+            let rules = model.rules(boid.species);
             let alignment = {
                 // Try to align with nearby boids.
-                let neighbor_dist = 50.0;
-
                 let mut sum = Vec2::ZERO;
                 let mut count = 0;
 
-                for other in &model.boids {
-                    let dist = boid.pos.distance(other.pos);
-                    if dist > 0.0 && dist < neighbor_dist {
-                        sum += other.vel;
-                        count += 1;
-                    }
+                for j in model.visible_neighbors(i, NeighborRule::Alignment) {
+                    sum += model.boids[j].vel;
+                    count += 1;
                 }
 
                 if count > 0 {
                     let avg_vel = sum / count as f32;
-                    let desired_vel = avg_vel.clamp_length(MAX_SPEED, MAX_SPEED);
+                    let desired_vel = avg_vel.clamp_length(rules.max_speed, rules.max_speed);
                     (desired_vel - boid.vel).clamp_length_max(MAX_FORCE)
                 } else {
                     vec2(0.0, 0.0)
                 }
             };
-            *accel += alignment * model.alignment_factor;
+            *accel += alignment * rules.alignment_factor;
         }
     }
 
-    if model.enable_cohesion && model.cohesion_factor > 0.0 {
-        for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
+    if model.enable_cohesion {
+        for (i, (boid, accel)) in model.boids.iter().zip(accels.iter_mut()).enumerate() {
+            let rules = model.rules(boid.species);
             let cohesion = {
                 // Try to move to the center of nearby boids.
-                let neighbor_dist = 50.0;
-
                 let mut sum = Vec2::ZERO;
                 let mut count = 0;
 
-                for other in &model.boids {
-                    let dist = boid.pos.distance(other.pos);
-                    if dist > 0.0 && dist < neighbor_dist {
-                        sum += other.pos;
-                        count += 1;
-                    }
+                // Sum offsets rather than positions so neighbors across the seam pull the right way.
+                for j in model.visible_neighbors(i, NeighborRule::Cohesion) {
+                    sum += model.boundary_mode.offset(boid.pos, model.boids[j].pos);
+                    count += 1;
                 }
 
                 if count > 0 {
-                    let avg_pos = sum / count as f32;
-                    let desired = (avg_pos - boid.pos).clamp_length(MAX_SPEED, MAX_SPEED);
+                    let avg_offset = sum / count as f32;
+                    let desired = avg_offset.clamp_length(rules.max_speed, rules.max_speed);
                     (desired - boid.vel).clamp_length_max(MAX_FORCE)
                 } else {
                     vec2(0.0, 0.0)
                 }
             };
-            *accel += cohesion * model.cohesion_factor;
+            *accel += cohesion * rules.cohesion_factor;
         }
     }
 
-    if model.enable_repulsion && model.repulsion_factor > 0.0 {
+    if model.enable_repulsion {
         for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
-            let repulsion = {
-                // Try to move away from repel nodes.
-                let repel_dist = 50.0;
+            let rules = model.rules(boid.species);
+            // Every node in range pushes or pulls on its own, so two nodes pull twice as hard.
+            let repulsion = model.nodes.iter()
+                .fold(Vec2::ZERO, |acc, node| acc + node.force(model.boundary_mode.offset(node.pos, boid.pos)));
+            *accel += repulsion * rules.repulsion_factor;
+        }
+    }
 
-                let mut sum = Vec2::ZERO;
-                let mut count = 0;
+    if model.enable_avoidance {
+        for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
+            let rules = model.rules(boid.species);
+            let avoidance = {
+                // Look ahead along our velocity and steer away from the first obstacle in the
+                // way. The sooner we'd hit it, the harder we turn.
+                let mut steer = Vec2::ZERO;
+                'probes: for i in 0..LOOK_AHEAD_PROBES {
+                    let t = i as f32 / (LOOK_AHEAD_PROBES - 1) as f32;
+                    let probe = boid.pos + boid.vel * LOOK_AHEAD_FRAMES * t;
+                    for obstacle in &model.obstacles {
+                        let edge_dist = obstacle.closest_edge_point(probe).distance(probe);
+                        if obstacle.contains(probe) || edge_dist < BOID_RADIUS {
+                            let desired = obstacle.away(probe) * rules.max_speed;
+                            steer = (desired - boid.vel).clamp_length_max(MAX_FORCE) * (1.0 - t * 0.5);
+                            break 'probes;
+                        }
+                    }
+                }
+                steer
+            };
+            *accel += avoidance * rules.avoidance_factor;
+        }
+    }
 
-                for repel in &model.repels {
-                    let dist = boid.pos.distance(repel.pos);
-                    if dist > 0.0 && dist < repel_dist {
-                        sum += repel.pos;
-                        count += 1;
+    // Prey eaten this frame, by index.
+    let mut eaten = Vec::new();
+    if model.enable_hunting {
+        for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
+            let rules = model.rules(boid.species);
+            let hunting = match boid.species {
+                Species::Prey => {
+                    // Run from nearby predators, more urgently from closer ones.
+                    let mut steer = Vec2::ZERO;
+                    for other in model.boids.iter().filter(|other| other.species == Species::Predator) {
+                        let offset = model.boundary_mode.offset(boid.pos, other.pos);
+                        let dist = offset.length();
+                        if dist > 0.0 && dist < rules.view_radius {
+                            steer -= offset.normalize() / dist;
+                        }
+                    }
+                    if steer != Vec2::ZERO {
+                        let desired = steer.clamp_length(rules.max_speed, rules.max_speed);
+                        (desired - boid.vel).clamp_length_max(MAX_FORCE)
+                    } else {
+                        Vec2::ZERO
+                    }
+                }
+                Species::Predator => {
+                    // Chase the closest prey we can see.
+                    let closest = model.boids.iter()
+                        .enumerate()
+                        .filter(|(_, other)| other.species == Species::Prey)
+                        .map(|(j, other)| (j, model.boundary_mode.offset(boid.pos, other.pos)))
+                        .filter(|(_, offset)| offset.length() < rules.view_radius)
+                        .min_by(|a, b| a.1.length().partial_cmp(&b.1.length()).unwrap());
+                    match closest {
+                        Some((j, offset)) => {
+                            if offset.length() < EAT_DIST {
+                                eaten.push(j);
+                            }
+                            let desired = offset.clamp_length(rules.max_speed, rules.max_speed);
+                            (desired - boid.vel).clamp_length_max(MAX_FORCE)
+                        }
+                        None => Vec2::ZERO,
                     }
                 }
+            };
+            *accel += hunting * rules.hunting_factor;
+        }
+    }
 
-                if count > 0 {
-                    let avg_pos = sum / count as f32;
-                    // Move away from the average position.
-                    let desired = -(avg_pos - boid.pos).clamp_length(MAX_SPEED, MAX_SPEED);
+    let mouse_goal = if model.follow_mouse { Some(mouse) } else { None };
+    if model.enable_goals && (mouse_goal.is_some() || !model.goals.is_empty()) {
+        for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
+            let rules = model.rules(boid.species);
+            let goal = {
+                // Head for the closest goal.
+                let closest = model.goals.iter()
+                    .copied()
+                    .chain(mouse_goal)
+                    .min_by(|a, b| boid.pos.distance(*a).partial_cmp(&boid.pos.distance(*b)).unwrap());
+                match closest {
+                    Some(goal) if goal != boid.pos => {
+                        let desired = (goal - boid.pos).clamp_length(rules.max_speed, rules.max_speed);
+                        (desired - boid.vel).clamp_length_max(MAX_FORCE)
+                    }
+                    _ => Vec2::ZERO,
+                }
+            };
+            *accel += goal * rules.goal_factor;
+        }
+    }
+
+    if let Some(leader_index) = model.leader() {
+        let leader = &model.boids[leader_index];
+        // Aim for a spot just behind the leader, slowing down on arrival so we don't overtake.
+        let target = leader.pos - leader.vel.normalize_or_zero() * LEADER_FOLLOW_DIST;
+        let boundary_mode = model.boundary_mode;
+        for (i, (boid, accel)) in model.boids.iter().zip(accels.iter_mut()).enumerate() {
+            if i == leader_index {
+                continue;
+            }
+            let rules = model.rules(boid.species);
+            let follow = {
+                let offset = boundary_mode.offset(boid.pos, target);
+                let dist = offset.length();
+                if dist > 0.0 {
+                    let speed = rules.max_speed * (dist / LEADER_FOLLOW_DIST).min(1.0);
+                    let desired = offset / dist * speed;
                     (desired - boid.vel).clamp_length_max(MAX_FORCE)
                 } else {
-                    vec2(0.0, 0.0)
+                    Vec2::ZERO
                 }
             };
-            *accel += repulsion * model.repulsion_factor;
+            *accel += follow * rules.leader_factor;
+        }
+    }
+
+    if model.boundary_mode == BoundaryMode::SoftWalls {
+        let half = vec2(WIDTH, HEIGHT) / 2.0;
+        for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
+            // Push back harder the closer we get to each wall.
+            let push = |coord: f32, half: f32| {
+                let near_min = (WALL_MARGIN - (coord + half)).max(0.0) / WALL_MARGIN;
+                let near_max = (WALL_MARGIN - (half - coord)).max(0.0) / WALL_MARGIN;
+                near_min - near_max
+            };
+            *accel += vec2(push(boid.pos.x, half.x), push(boid.pos.y, half.y)) * WALL_FORCE;
         }
     }
 
     for (boid, accel) in model.boids.iter_mut().zip(accels.iter()) {
+        let max_speed = match boid.species {
+            Species::Prey => model.prey_rules.max_speed,
+            Species::Predator => model.predator_rules.max_speed,
+        };
+
         // Update our physics.
         boid.vel += *accel;
-        boid.vel = boid.vel.clamp_length_max(MAX_SPEED);
+        boid.vel = boid.vel.clamp_length_max(max_speed);
         boid.pos += boid.vel;
+        boid.trail.push_front(boid.pos);
+        boid.trail.truncate(TRAIL_LENGTH);
 
-        // Wrap around if we left the window border.
-        // Horizontal check.
-        if boid.pos.x < -(WIDTH / 2.0) - BOID_RADIUS {
-            boid.pos.x = (WIDTH / 2.0) + BOID_RADIUS
-        } else if boid.pos.x > (WIDTH / 2.0) + BOID_RADIUS {
-            boid.pos.x = -(WIDTH / 2.0) - BOID_RADIUS
-        }
-        // Vertical check.
-        if boid.pos.y < -(HEIGHT / 2.0) - BOID_RADIUS {
-            boid.pos.y = (HEIGHT / 2.0) + BOID_RADIUS
-        } else if boid.pos.y > (HEIGHT / 2.0) + BOID_RADIUS {
-            boid.pos.y = -(HEIGHT / 2.0) - BOID_RADIUS
+        match model.boundary_mode {
+            BoundaryMode::Wrap => {
+                // Wrap around if we left the window border.
+                // Horizontal check.
+                if boid.pos.x < -(WIDTH / 2.0) - BOID_RADIUS {
+                    boid.pos.x = (WIDTH / 2.0) + BOID_RADIUS
+                } else if boid.pos.x > (WIDTH / 2.0) + BOID_RADIUS {
+                    boid.pos.x = -(WIDTH / 2.0) - BOID_RADIUS
+                }
+                // Vertical check.
+                if boid.pos.y < -(HEIGHT / 2.0) - BOID_RADIUS {
+                    boid.pos.y = (HEIGHT / 2.0) + BOID_RADIUS
+                } else if boid.pos.y > (HEIGHT / 2.0) + BOID_RADIUS {
+                    boid.pos.y = -(HEIGHT / 2.0) - BOID_RADIUS
+                }
+            }
+            BoundaryMode::Bounce => {
+                // Flip our velocity if we hit the window border, unless we're already heading back.
+                let half = vec2(WIDTH, HEIGHT) / 2.0 - Vec2::splat(BOID_RADIUS);
+                if boid.pos.x.abs() > half.x && boid.pos.x * boid.vel.x > 0.0 {
+                    boid.vel.x = -boid.vel.x;
+                }
+                if boid.pos.y.abs() > half.y && boid.pos.y * boid.vel.y > 0.0 {
+                    boid.vel.y = -boid.vel.y;
+                }
+                boid.pos = boid.pos.clamp(-half, half);
+            }
+            BoundaryMode::SoftWalls => {
+                // The walls should turn us around before this, but don't let anyone escape.
+                let half = vec2(WIDTH, HEIGHT) / 2.0;
+                boid.pos = boid.pos.clamp(-half, half);
+            }
+            BoundaryMode::Open => {}
         }
     }
+
+    if model.boundary_mode == BoundaryMode::Open && !model.boids.is_empty() {
+        // Ease the camera toward the middle of the flock.
+        let centroid = model.boids.iter().fold(Vec2::ZERO, |acc, boid| acc + boid.pos) / model.boids.len() as f32;
+        model.camera += (centroid - model.camera) * CAMERA_FOLLOW;
+    }
+
+    // Two predators can catch the same prey, so dedup before removing back to front.
+    eaten.sort_unstable();
+    eaten.dedup();
+    for &i in eaten.iter().rev() {
+        model.boids.remove(i);
+        // Keep following the same boid now that the ones after it have shifted down.
+        model.debug_boid = match model.debug_boid {
+            Some(debug) if debug == i => None,
+            Some(debug) if debug > i => Some(debug - 1),
+            debug => debug,
+        };
+    }
+    model.eaten += eaten.len() as u32;
+
+    model.step += 1;
+    model.record_stats();
 }
 
 fn view(app: &App, model: &Model, frame: Frame) {
-    let draw = app.draw();
+    // Draw the world through the camera, and overlays straight to the window.
+    let screen = app.draw();
+    let draw = screen.xy(-model.camera);
+
+    screen.background().color(Rgb::new(50u8, 50, 50));
 
-    draw.background().color(Rgb::new(50u8, 50, 50));
+    if model.boundary_mode == BoundaryMode::Open {
+        let rect = app.window_rect().shift(model.camera);
+        let grid_color = Rgba::new(1.0, 1.0, 1.0, 0.05);
+        let first = (vec2(rect.left(), rect.bottom()) / GRID_SPACING).floor() * GRID_SPACING;
+        let mut x = first.x;
+        while x < rect.right() {
+            draw.line().start(vec2(x, rect.bottom())).end(vec2(x, rect.top())).color(grid_color);
+            x += GRID_SPACING;
+        }
+        let mut y = first.y;
+        while y < rect.top() {
+            draw.line().start(vec2(rect.left(), y)).end(vec2(rect.right(), y)).color(grid_color);
+            y += GRID_SPACING;
+        }
+    }
 
-    let neighbor_dist = 50.0;
+    let neighbor_dist = NEIGHBOR_DIST;
 
-    let happy_boid_color = Rgba::new(95.0 / 255.0, 219.0 / 255.0, 0.0 / 255.0, 200.0 / 255.0)
-        .into_linear();
+    let happy_boid_color = model.prey_rules.color.into_linear();
     let sad_boid_color = Rgba::new(0.0 / 255.0, 146.0 / 255.0, 219.0 / 255.0, 200.0 / 255.0)
         .into_linear();
     let gradient = Gradient::new(vec![sad_boid_color, happy_boid_color]);
 
-    // Draw repel nodes first.
-    for repel in &model.repels {
+    // Draw obstacles first, highlighting the one under the mouse while editing.
+    let mouse = model.mouse();
+    let hovered = if model.edit_mode { model.pick_obstacle(mouse) } else { None };
+    for (i, obstacle) in model.obstacles.iter().enumerate() {
+        let color = if hovered == Some(i) {
+            Rgba::new(0.6, 0.6, 0.6, 1.0)
+        } else {
+            Rgba::new(0.4, 0.4, 0.4, 1.0)
+        };
+        obstacle.draw(&draw, color);
+    }
+
+    if model.edit_mode {
+        // Preview whatever is being placed.
+        let preview = Rgba::new(0.6, 0.6, 0.6, 0.5);
+        match model.drag {
+            Some(Drag::Circle { center }) => Obstacle::Circle {
+                center,
+                radius: center.distance(mouse),
+            }.draw(&draw, preview),
+            Some(Drag::Rect { start }) => Obstacle::Rect(Rect::from_corners(start, mouse)).draw(&draw, preview),
+            _ => {}
+        }
+        if !model.polygon_points.is_empty() {
+            draw.polyline()
+                .weight(1.0)
+                .points(model.polygon_points.iter().copied().chain(std::iter::once(mouse)))
+                .color(WHITE);
+        }
+    }
+
+    // Draw force nodes next, with a faint ring showing how far they reach.
+    for (i, node) in model.nodes.iter().enumerate() {
+        let color = match node.kind {
+            NodeKind::Repel => Rgba::new(1.0, 0.0, 0.0, 1.0),
+            NodeKind::Attract => Rgba::new(0.0, 0.8, 1.0, 1.0),
+        };
+        let selected = model.selected_node == Some(i);
+        draw.ellipse()
+            .radius(node.radius)
+            .no_fill()
+            .stroke(Rgba::new(color.red, color.green, color.blue, if selected { 0.6 } else { 0.2 }))
+            .stroke_weight(1.0)
+            .xy(node.pos);
         draw.ellipse()
             .radius(BOID_RADIUS)
-            .color(nannou::color::RED)
-            .xy(repel.pos);
+            .color(color)
+            .xy(node.pos);
+        if selected {
+            draw.ellipse()
+                .radius(BOID_RADIUS + 3.0)
+                .no_fill()
+                .stroke(WHITE)
+                .stroke_weight(1.5)
+                .xy(node.pos);
+        }
+    }
+
+    for &goal in &model.goals {
+        draw.ellipse()
+            .radius(BOID_RADIUS * 1.5)
+            .no_fill()
+            .stroke(YELLOW)
+            .stroke_weight(2.0)
+            .xy(goal);
+    }
+
+    if let Some(leader) = model.leader() {
+        draw.ellipse()
+            .radius(BOID_RADIUS * 4.0)
+            .no_fill()
+            .stroke(WHITE)
+            .xy(model.boids[leader].pos);
+    }
+
+    match model.render_mode {
+        RenderMode::Trails => {
+            for boid in &model.boids {
+                let color = match boid.species {
+                    Species::Prey => model.prey_rules.color,
+                    Species::Predator => model.predator_rules.color,
+                };
+                // Fade out toward the oldest position. Skip segments that jump across the window
+                // from wrapping.
+                let len = boid.trail.len();
+                for (k, (a, b)) in boid.trail.iter().zip(boid.trail.iter().skip(1)).enumerate() {
+                    if a.distance(*b) > NEIGHBOR_DIST {
+                        continue;
+                    }
+                    let alpha = color.alpha * (1.0 - k as f32 / len as f32);
+                    draw.line()
+                        .start(*a)
+                        .end(*b)
+                        .weight(1.5)
+                        .color(Rgba::new(color.red, color.green, color.blue, alpha));
+                }
+            }
+        }
+        RenderMode::Heatmap => {
+            // Splat each boid into the four closest cells of a grid covering the window, weighted by
+            // how close it is to each cell's center.
+            let rect = app.window_rect().shift(model.camera);
+            let cols = (rect.w() / HEATMAP_CELL).ceil() as usize + 1;
+            let rows = (rect.h() / HEATMAP_CELL).ceil() as usize + 1;
+            let mut density = vec![0.0f32; cols * rows];
+            for boid in &model.boids {
+                let fx = (boid.pos.x - rect.left()) / HEATMAP_CELL - 0.5;
+                let fy = (boid.pos.y - rect.bottom()) / HEATMAP_CELL - 0.5;
+                let (i0, j0) = (fx.floor(), fy.floor());
+                let (tx, ty) = (fx - i0, fy - j0);
+                let corners = [(0, 0, (1.0 - tx) * (1.0 - ty)), (1, 0, tx * (1.0 - ty)), (0, 1, (1.0 - tx) * ty), (1, 1, tx * ty)];
+                for (di, dj, weight) in corners {
+                    let (i, j) = (i0 as i64 + di, j0 as i64 + dj);
+                    if i >= 0 && j >= 0 && (i as usize) < cols && (j as usize) < rows {
+                        density[j as usize * cols + i as usize] += weight;
+                    }
+                }
+            }
+            for j in 0..rows {
+                for i in 0..cols {
+                    let value = (density[j * cols + i] / HEATMAP_MAX_DENSITY).min(1.0);
+                    if value <= 0.0 {
+                        continue;
+                    }
+                    // Go from blue through red to yellow as it gets more crowded.
+                    draw.rect()
+                        .x_y(
+                            rect.left() + (i as f32 + 0.5) * HEATMAP_CELL,
+                            rect.bottom() + (j as f32 + 0.5) * HEATMAP_CELL,
+                        )
+                        .w_h(HEATMAP_CELL, HEATMAP_CELL)
+                        .hsva(0.66 - value * 0.5, 0.9, value.sqrt(), 0.8);
+                }
+            }
+        }
+        _ => {}
     }
 
-    // Draw boids.
+    // Draw boids. Predators are bigger.
     let (v1, v2, v3) = (
         vec2(2.0 * BOID_RADIUS, 0.0),
         vec2(-2.0 * BOID_RADIUS, -BOID_RADIUS),
         vec2(-2.0 * BOID_RADIUS, BOID_RADIUS),
     );
-    for boid in &model.boids {
-        let neighbor_count = model.boids.iter()
-            .filter(|other| boid.pos.distance(other.pos) < neighbor_dist)
-            .count();
-        let gradient_color = map_range(neighbor_count as f32, 0.0, 8.0, 0.0, 1.0);
-        draw.tri()
-            .color(gradient.get(gradient_color))
-            .points(v1, v2, v3)
-            .xy(boid.pos)
-            .rotate(boid.vel.angle());
-    }
-
-    draw.to_frame(app, &frame)
+    let clusters = if model.render_mode == RenderMode::Clusters { model.clusters() } else { Vec::new() };
+    for (i, boid) in model.boids.iter().enumerate() {
+        match boid.species {
+            Species::Prey => {
+                let color: LinSrgba = match model.render_mode {
+                    RenderMode::Neighbors | RenderMode::Trails => {
+                        let neighbor_count = model.boids.iter()
+                            .filter(|other| {
+                                other.species == Species::Prey
+                                    && model.boundary_mode.offset(boid.pos, other.pos).length() < neighbor_dist
+                            })
+                            .count();
+                        let gradient_color = map_range(neighbor_count as f32, 0.0, 8.0, 0.0, 1.0);
+                        gradient.get(gradient_color)
+                    }
+                    RenderMode::Heading => {
+                        let hue = (boid.vel.angle() / TWO_PI).rem_euclid(1.0);
+                        hsva(hue, 0.7, 1.0, 0.8).into_lin_srgba()
+                    }
+                    RenderMode::Speed => {
+                        let brightness = map_range(boid.vel.length(), 0.0, model.prey_rules.max_speed, 0.15, 1.0);
+                        hsva(0.3, 0.8, brightness, 0.8).into_lin_srgba()
+                    }
+                    RenderMode::Heatmap => {
+                        // The heatmap is the point, so keep boids faint.
+                        Rgba::new(1.0, 1.0, 1.0, 0.3).into_linear()
+                    }
+                    RenderMode::Clusters => {
+                        // Spread hues around the wheel by the golden ratio so neighboring labels
+                        // look different.
+                        let hue = (clusters[i] as f32 * 0.618_034).rem_euclid(1.0);
+                        hsva(hue, 0.7, 1.0, 0.8).into_lin_srgba()
+                    }
+                };
+                draw.tri()
+                    .color(color)
+                    .points(v1, v2, v3)
+                    .xy(boid.pos)
+                    .rotate(boid.vel.angle());
+            }
+            Species::Predator => {
+                draw.tri()
+                    .color(model.predator_rules.color)
+                    .points(v1 * 2.0, v2 * 2.0, v3 * 2.0)
+                    .xy(boid.pos)
+                    .rotate(boid.vel.angle());
+            }
+        }
+    }
+
+    // Show what the debug boid sees: a cone per rule and a line to each neighbor it counts.
+    if let Some(i) = model.debug_boid.filter(|&i| i < model.boids.len()) {
+        let boid = &model.boids[i];
+        let heading = boid.vel.angle();
+        for &rule in NEIGHBOR_RULES.iter().rev() {
+            let fov = model.fov(rule);
+            let color = rule.debug_color();
+            let segments = 32;
+            let arc = (0..=segments).map(|k| {
+                let angle = heading - fov / 2.0 + fov * k as f32 / segments as f32;
+                boid.pos + vec2(angle.cos(), angle.sin()) * rule.radius()
+            });
+            // Leave out the center for a full circle so there's no seam drawn through it.
+            let outline: Vec<Vec2> = if fov >= TWO_PI {
+                arc.collect()
+            } else {
+                std::iter::once(boid.pos).chain(arc).collect()
+            };
+            draw.polygon()
+                .color(Rgba::new(color.red, color.green, color.blue, 0.1))
+                .points(outline.iter().copied());
+            draw.polyline()
+                .weight(1.0)
+                .color(Rgba::new(color.red, color.green, color.blue, 0.5))
+                .points_closed(outline);
+
+            for j in model.visible_neighbors(i, rule) {
+                // Point across the seam at neighbors on the other side.
+                draw.line()
+                    .start(boid.pos)
+                    .end(boid.pos + model.boundary_mode.offset(boid.pos, model.boids[j].pos))
+                    .weight(1.0)
+                    .color(color);
+            }
+        }
+    }
+
+    // Population counters in the top left corner.
+    let rect = app.window_rect();
+    let overlay = [
+        (format!("Prey: {}", model.count(Species::Prey)), model.prey_rules.color),
+        (format!("Predators: {}", model.count(Species::Predator)), model.predator_rules.color),
+        (format!("Eaten: {}", model.eaten), Rgba::new(1.0, 1.0, 1.0, 1.0)),
+    ];
+    for (i, (text, color)) in overlay.iter().enumerate() {
+        screen.text(text)
+            .left_justify()
+            .color(*color)
+            .w_h(200.0, 20.0)
+            .x_y(rect.left() + 110.0, rect.top() - 15.0 - i as f32 * 18.0);
+    }
+
+    // What's being written to disk or played back, in the top right corner.
+    let mut status = Vec::new();
+    if model.telemetry.is_some() {
+        status.push("TELEMETRY".to_string());
+    }
+    if model.recorder.is_some() {
+        status.push(format!("REC step {}", model.step));
+    }
+    if let Some(replay) = &model.replay {
+        let paused = if replay.paused { " (paused)" } else { "" };
+        status.push(format!("REPLAY step {} / {}{}", model.step, replay.recording.end_step(), paused));
+        if replay.frames_dir.is_some() {
+            status.push("RENDERING FRAMES".to_string());
+        }
+    }
+    for (i, text) in status.iter().enumerate() {
+        screen.text(text)
+            .right_justify()
+            .color(RED)
+            .w_h(250.0, 20.0)
+            .x_y(rect.right() - 135.0, rect.top() - 15.0 - i as f32 * 18.0);
+    }
+
+    if model.show_stats && !model.stats_history.is_empty() {
+        // Sparklines stacked in the bottom left corner, with the latest neighbor counts next to them.
+        let history = &model.stats_history;
+        let series = |f: fn(&FlockStats) -> f32| history.iter().map(f).collect::<Vec<f32>>();
+        let max_of = |values: &[f32]| values.iter().copied().fold(0.0, f32::max);
+
+        let polarization = series(|stats| stats.polarization);
+        let nearest = series(|stats| stats.mean_nearest_dist);
+        let clusters = series(|stats| stats.clusters as f32);
+        let speed = series(|stats| stats.avg_speed);
+        let neighbors = series(FlockStats::mean_neighbors);
+        let lines = [
+            ("polarization", polarization.as_slice(), 1.0),
+            ("nearest dist", nearest.as_slice(), max_of(&nearest)),
+            ("clusters", clusters.as_slice(), max_of(&clusters)),
+            ("avg speed", speed.as_slice(), model.prey_rules.max_speed),
+            ("neighbors", neighbors.as_slice(), max_of(&neighbors)),
+        ];
+        let (w, h, gap) = (200.0, 28.0, 4.0);
+        for (i, (label, values, max)) in lines.iter().enumerate() {
+            let spark_rect = Rect::from_x_y_w_h(
+                rect.left() + 10.0 + w / 2.0,
+                rect.bottom() + 10.0 + h / 2.0 + i as f32 * (h + gap),
+                w,
+                h,
+            );
+            draw_sparkline(&screen, spark_rect, values, *max, label, model.prey_rules.color);
+        }
+
+        let latest = &history[history.len() - 1];
+        let most = latest.neighbor_counts.iter().copied().max().unwrap_or(0).max(1);
+        let bar_w = 10.0;
+        let hist_h = lines.len() as f32 * (h + gap) - gap;
+        let left = rect.left() + 20.0 + w;
+        let bottom = rect.bottom() + 10.0;
+        for (n, &count) in latest.neighbor_counts.iter().enumerate() {
+            let bar_h = (count as f32 / most as f32 * (hist_h - 14.0)).max(1.0);
+            screen.rect()
+                .x_y(left + (n as f32 + 0.5) * (bar_w + 2.0), bottom + 14.0 + bar_h / 2.0)
+                .w_h(bar_w, bar_h)
+                .color(model.prey_rules.color);
+        }
+        screen.text("neighbors")
+            .color(WHITE)
+            .font_size(11)
+            .w_h(NEIGHBOR_BUCKETS as f32 * (bar_w + 2.0), 14.0)
+            .x_y(left + NEIGHBOR_BUCKETS as f32 * (bar_w + 2.0) / 2.0, bottom + 7.0);
+    }
+
+    screen.to_frame(app, &frame)
         .unwrap();
+
+    // Frames aren't saved while paused, so there's one per step.
+    let frames_dir = model.replay.as_ref()
+        .filter(|replay| !replay.paused)
+        .and_then(|replay| replay.frames_dir.as_ref());
+    if let Some(dir) = frames_dir {
+        app.main_window().capture_frame(dir.join(format!("{:06}.png", model.step)));
+    }
 }
 
 fn main() {
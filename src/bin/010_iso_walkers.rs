@@ -2,12 +2,17 @@
 // https://www.reddit.com/r/proceduralgeneration/comments/f16ml3/isometric_random_walkers/
 // https://github.com/ShriRambo/p5Sketches/blob/master/Iso%20random%20walker/sketch.js
 
+use std::collections::HashMap;
+
 use nannou::prelude::*;
 use nannou::rand::rand::{
     self,
     seq::SliceRandom,
 };
+use nannou::wgpu;
+use nannou::window::Window;
 use nannou::winit;
+use nannou::Draw;
 
 const WIDTH: f32 = 800.0;
 const HEIGHT: f32 = 800.0;
@@ -21,93 +26,432 @@ const WALKER_PALETTE: &[(u8, u8, u8)] = &[
     (0xe7, 0x05, 0xbe),
     (0x03, 0xa4, 0xff),
 ];
-const TURN_CHANCE: f32 = 0.05;
+const WALKER_SPEED: f32 = 2.0;
+// Chance to turn each time a walker reaches a lattice node.
+const TURN_CHANCE: f32 = 0.4;
+
+// Length of a lattice edge in pixels.
+const CELL_SIZE: f32 = 20.0;
+// The lattice wraps around this many half-cell steps to either side of, and above and below, the
+// origin. This covers the window plus a little extra. Rows are a multiple of 3 so cube centers line
+// up across the seam.
+const GRID_COLS: i32 = 24;
+const GRID_ROWS: i32 = 42;
+
+// How many frames a trail takes to fade into the background, and the limits when adjusting it.
+const TRAIL_LENGTH: u32 = 60;
+const MIN_TRAIL_LENGTH: u32 = 5;
+const MAX_TRAIL_LENGTH: u32 = 2000;
+// Fade this close to the background color over a trail's length.
+const TRAIL_CUTOFF: f32 = 1.0 / 255.0;
+
+// Brightness of the top, left and right faces of a cube.
+const FACE_SHADES: [f32; 3] = [1.0, 0.75, 0.5];
+
+// Lattice offsets for the six directions, counter-clockwise starting at 30°. Each is CELL_SIZE
+// long in pixels.
+const DIRECTIONS: [(i32, i32); 6] = [(1, 0), (1, 1), (0, 1), (-1, 0), (-1, -1), (0, -1)];
+
+// Lattice coordinates: a node (i, j) sits at i * A + j * B, where A points 30° up from the x axis
+// and B 150°.
+type Node = (i32, i32);
+
+fn to_world(node: Node) -> Vec2 {
+    let a = vec2((PI / 6.0).cos(), (PI / 6.0).sin()) * CELL_SIZE;
+    let b = vec2(-a.x, a.y);
+    a * node.0 as f32 + b * node.1 as f32
+}
+
+fn direction(dir: usize) -> Vec2 {
+    to_world(DIRECTIONS[dir % 6])
+}
+
+fn step(node: Node, dir: usize) -> Node {
+    let (di, dj) = DIRECTIONS[dir % 6];
+    (node.0 + di, node.1 + dj)
+}
+
+// Wraps a node back into the lattice area around the origin.
+fn wrap(node: Node) -> Node {
+    // u and v are horizontal and vertical steps, and always have the same parity. Both periods
+    // are even so wrapping keeps it that way.
+    let u = (node.0 - node.1 + GRID_COLS).rem_euclid(2 * GRID_COLS) - GRID_COLS;
+    let v = (node.0 + node.1 + GRID_ROWS).rem_euclid(2 * GRID_ROWS) - GRID_ROWS;
+    ((u + v) / 2, (v - u) / 2)
+}
+
+// Cube centers form a sub-lattice with every third node. Every other node is a corner shared by
+// three cubes.
+fn is_cube_center(node: Node) -> bool {
+    (node.0 + node.1).rem_euclid(3) == 0
+}
+
+fn shade(color: Srgb<u8>, factor: f32) -> Srgb<u8> {
+    let (r, g, b) = color.into_components();
+    Srgb::new(
+        (r as f32 * factor) as u8,
+        (g as f32 * factor) as u8,
+        (b as f32 * factor) as u8,
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RenderMode {
+    // The walkers' fading trails.
+    Lines,
+    // Every visited node fills a rhombus facing the way it was last crossed.
+    Rhombi,
+    // Visited nodes fill the cube they belong to.
+    Cubes,
+}
+
+impl RenderMode {
+    fn next(self) -> Self {
+        match self {
+            RenderMode::Lines => RenderMode::Rhombi,
+            RenderMode::Rhombi => RenderMode::Cubes,
+            RenderMode::Cubes => RenderMode::Lines,
+        }
+    }
+}
+
+// How walkers react to nodes that have already been visited.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OccupancyRule {
+    Ignore,
+    Avoid,
+    Follow,
+}
+
+impl OccupancyRule {
+    fn next(self) -> Self {
+        match self {
+            OccupancyRule::Ignore => OccupancyRule::Avoid,
+            OccupancyRule::Avoid => OccupancyRule::Follow,
+            OccupancyRule::Follow => OccupancyRule::Ignore,
+        }
+    }
+}
+
+// The last walker to pass through a node.
+struct Tile {
+    color: Srgb<u8>,
+    // Which of the three lattice axes it was moving along.
+    axis: usize,
+}
 
-// Position and direction are in pixel coordinates.
 struct IsoWalker {
-    pos: Vec2,
-    dir: Vec2,
+    // The node we last passed and the direction we're heading from it.
+    node: Node,
+    dir: usize,
+    // How far along the current edge we are, from 0 to 1.
+    progress: f32,
     color: Srgb<u8>,
 }
 
 impl IsoWalker {
     fn new() -> Self {
-        let x = random_range(-WIDTH / 2.0, WIDTH / 2.0);
-        let y = random_range(-HEIGHT / 2.0, HEIGHT / 2.0);
-        let first_rot = PI / 6.0;
-        let second_rot = random_range(0, 3) as f32 * TAU / 3.0;
-        let dir = vec2(2.0, 0.0).rotate(first_rot).rotate(second_rot);
+        let u = random_range(-GRID_COLS, GRID_COLS);
+        let mut v = random_range(-GRID_ROWS, GRID_ROWS);
+        if (u + v) % 2 != 0 {
+            v += 1;
+        }
+        let node = wrap(((u + v) / 2, (v - u) / 2));
+        // Start off heading 30°, 150° or 270°.
+        let dir = random_range(0, 3) * 2;
         let color = WALKER_PALETTE.choose(&mut rand::thread_rng()).unwrap();
         Self {
-            pos: vec2(x, y),
+            node,
             dir,
-            color: Srgb::from_components(color.clone()),
+            progress: 0.0,
+            color: Srgb::from_components(*color),
+        }
+    }
+
+    // Position in pixel coordinates.
+    fn pos(&self) -> Vec2 {
+        to_world(self.node) + direction(self.dir) * self.progress
+    }
+
+    // Picks the direction to leave a node in.
+    fn choose_dir(&self, tiles: &HashMap<Node, Tile>, rule: OccupancyRule) -> usize {
+        // Turns are always a multiple of 120°.
+        let straight = self.dir;
+        let mut turns = [(self.dir + 2) % 6, (self.dir + 4) % 6];
+        turns.shuffle(&mut rand::thread_rng());
+
+        let wants = match rule {
+            OccupancyRule::Ignore => None,
+            OccupancyRule::Avoid => Some(false),
+            OccupancyRule::Follow => Some(true),
+        };
+        if let Some(wants_occupied) = wants {
+            let occupied = |dir: usize| tiles.contains_key(&wrap(step(self.node, dir)));
+            if occupied(straight) != wants_occupied {
+                if let Some(&dir) = turns.iter().find(|&&dir| occupied(dir) == wants_occupied) {
+                    return dir;
+                }
+            }
+        }
+
+        if random_f32() < TURN_CHANCE {
+            turns[0]
+        } else {
+            straight
         }
     }
 }
 
+// Walker trails are drawn into an offscreen texture that keeps its contents between frames. Each
+// frame it gets faded toward the background with an explicit decay pass before new segments are
+// drawn on top, then the texture is copied to the window.
+struct Trails {
+    texture: wgpu::Texture,
+    draw: Draw,
+    renderer: nannou::draw::Renderer,
+    reshaper: wgpu::TextureReshaper,
+    // Clear to the background on the next render instead of decaying.
+    needs_clear: bool,
+}
+
+impl Trails {
+    fn new(window: &Window) -> Self {
+        let device = window.device();
+        let sample_count = window.msaa_samples();
+        let texture = wgpu::TextureBuilder::new()
+            .size([WIDTH as u32, HEIGHT as u32])
+            .usage(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
+            .sample_count(sample_count)
+            // Use a float format so long trails can fade all the way out instead of getting stuck
+            // a few 8-bit steps away from the background.
+            .format(wgpu::TextureFormat::Rgba16Float)
+            .build(device);
+        let renderer = nannou::draw::RendererBuilder::new()
+            .build_from_texture_descriptor(device, texture.descriptor());
+        let texture_view = texture.view().build();
+        let reshaper = wgpu::TextureReshaper::new(
+            device,
+            &texture_view,
+            sample_count,
+            texture.sample_type(),
+            sample_count,
+            Frame::TEXTURE_FORMAT,
+        );
+
+        Self {
+            texture,
+            draw: Draw::new(),
+            renderer,
+            reshaper,
+            needs_clear: true,
+        }
+    }
+
+    // Fades the previous contents, draws the latest walker segments and renders to the texture.
+    fn render(&mut self, window: &Window, walkers: &[IsoWalker], decay: Option<f32>) {
+        let draw = &self.draw;
+        draw.reset();
+
+        let bg = Srgb::new(BG_COLOR, BG_COLOR, BG_COLOR);
+        if self.needs_clear {
+            draw.background().color(bg);
+            self.needs_clear = false;
+        } else if let Some(decay) = decay {
+            let bg: Rgb = bg.into_format();
+            draw.rect()
+                .color(Rgba::new(bg.red, bg.green, bg.blue, decay))
+                .w_h(WIDTH, HEIGHT);
+        }
+
+        for walker in walkers {
+            let dir = direction(walker.dir).normalize();
+            draw.line()
+                .color(walker.color)
+                .stroke_weight(3.0)
+                .caps_round()
+                .start(walker.pos())
+                .end(walker.pos() + dir * WALKER_SPEED);
+        }
+
+        let device = window.device();
+        let ce_desc = wgpu::CommandEncoderDescriptor {
+            label: Some("trails renderer"),
+        };
+        let mut encoder = device.create_command_encoder(&ce_desc);
+        self.renderer.render_to_texture(device, &mut encoder, draw, &self.texture);
+        window.queue().submit(Some(encoder.finish()));
+    }
+}
+
 struct Model {
     walkers: Vec<IsoWalker>,
+    tiles: HashMap<Node, Tile>,
+
+    render_mode: RenderMode,
+    occupancy_rule: OccupancyRule,
+
+    trails: Trails,
+    trail_length: u32,
+    // Long exposure: never fade trails.
+    no_decay: bool,
+}
+
+impl Model {
+    fn reset(&mut self) {
+        self.walkers = (0..NUM_WALKERS).map(|_| IsoWalker::new()).collect();
+        self.tiles.clear();
+        self.trails.needs_clear = true;
+    }
+
+    // How much of the background to blend in each frame, or None to keep trails forever.
+    fn decay_rate(&self) -> Option<f32> {
+        if self.no_decay {
+            None
+        } else {
+            Some(1.0 - TRAIL_CUTOFF.powf(1.0 / self.trail_length as f32))
+        }
+    }
 }
 
 fn model(app: &App) -> Model {
     let window_builder = winit::window::WindowBuilder::new()
         .with_resizable(false);
-    let _window = app.new_window()
+    let window_id = app.new_window()
         .window(window_builder)
         .size_pixels(WIDTH as u32, HEIGHT as u32)
         .title("Iso Walkers")
+        .event(event)
         .view(view)
         .build()
         .unwrap();
+    let window = app.window(window_id).unwrap();
 
     Model {
         walkers: (0..NUM_WALKERS).map(|_| IsoWalker::new()).collect(),
+        tiles: HashMap::new(),
+
+        render_mode: RenderMode::Lines,
+        occupancy_rule: OccupancyRule::Ignore,
+
+        trails: Trails::new(&window),
+        trail_length: TRAIL_LENGTH,
+        no_decay: false,
     }
 }
 
-fn update(_app: &App, model: &mut Model, _update: Update) {
+fn event(_app: &App, model: &mut Model, event: WindowEvent) {
+    match event {
+        WindowEvent::KeyPressed(Key::M) => {
+            model.render_mode = model.render_mode.next();
+            println!("Render mode: {:?}", model.render_mode);
+            // Don't pick up stale trails from the last time we were drawing lines.
+            model.trails.needs_clear = true;
+        }
+        WindowEvent::KeyPressed(Key::O) => {
+            model.occupancy_rule = model.occupancy_rule.next();
+            println!("Occupancy rule: {:?}", model.occupancy_rule);
+        }
+        WindowEvent::KeyPressed(Key::R) => {
+            model.reset();
+        }
+        WindowEvent::KeyPressed(Key::Up) => {
+            model.trail_length = (model.trail_length * 3 / 2).min(MAX_TRAIL_LENGTH);
+            println!("Trail length: {} frames (decay rate {:.4})", model.trail_length, model.decay_rate().unwrap_or(0.0));
+        }
+        WindowEvent::KeyPressed(Key::Down) => {
+            model.trail_length = (model.trail_length * 2 / 3).max(MIN_TRAIL_LENGTH);
+            println!("Trail length: {} frames (decay rate {:.4})", model.trail_length, model.decay_rate().unwrap_or(0.0));
+        }
+        WindowEvent::KeyPressed(Key::D) => {
+            model.no_decay = !model.no_decay;
+            println!("No decay: {}", model.no_decay);
+        }
+        _ => {}
+    }
+}
+
+fn update(app: &App, model: &mut Model, _update: Update) {
     for walker in &mut model.walkers {
-        walker.pos += walker.dir;
+        walker.progress += WALKER_SPEED / CELL_SIZE;
 
-        if random_f32() < TURN_CHANCE {
-            let rotation = random_range(0, 3) as f32 * TAU / 3.0;
-            walker.dir = walker.dir.rotate(rotation);
+        // Only ever change direction on a lattice node so we stay on the grid.
+        if walker.progress >= 1.0 {
+            walker.progress -= 1.0;
+            walker.node = wrap(step(walker.node, walker.dir));
+            model.tiles.insert(walker.node, Tile {
+                color: walker.color,
+                axis: walker.dir % 3,
+            });
+            walker.dir = walker.choose_dir(&model.tiles, model.occupancy_rule);
         }
+    }
 
-        if walker.pos.x < -WIDTH / 2.0 {
-            walker.pos.x += WIDTH;
-        } else if walker.pos.x > WIDTH / 2.0 {
-            walker.pos.x -= WIDTH;
-        }
-        if walker.pos.y < -HEIGHT / 2.0 {
-            walker.pos.y += HEIGHT;
-        } else if walker.pos.y > HEIGHT / 2.0 {
-            walker.pos.y -= HEIGHT;
-        }
+    if model.render_mode == RenderMode::Lines {
+        let decay = model.decay_rate();
+        model.trails.render(&app.main_window(), &model.walkers, decay);
     }
 }
 
-fn view(app: &App, model: &Model, frame: Frame) {
-    let draw = app.draw();
+// Draws the rhombus with one corner at corner whose sides run along directions dir and dir + 2.
+fn draw_face(draw: &Draw, corner: Vec2, dir: usize, color: Srgb<u8>) {
+    draw.quad()
+        .points(
+            corner,
+            corner + direction(dir),
+            corner + direction(dir + 1),
+            corner + direction(dir + 2),
+        )
+        .color(color);
+}
 
-    if app.elapsed_frames() < 1 {
-        // Only set background color on the first frame. Afterwards use a transparent rectangle.
-        let color = Srgb::new(BG_COLOR, BG_COLOR, BG_COLOR);
-        draw.background().color(color);
-    } else {
-        draw.rect()
-            .rgba8(BG_COLOR, BG_COLOR, BG_COLOR, BG_COLOR)
-            .w_h(WIDTH, HEIGHT);
+fn draw_cube(draw: &Draw, center: Vec2, color: Srgb<u8>) {
+    // Top, left and right faces.
+    for (face, &dir) in [0, 2, 4].iter().enumerate() {
+        draw_face(draw, center, dir, shade(color, FACE_SHADES[face]));
     }
+}
 
-    for walker in &model.walkers {
-        draw.line()
-            .color(walker.color)
-            .stroke_weight(3.0)
-            .caps_round()
-            .start(walker.pos)
-            .end(walker.pos + walker.dir);
+fn view(app: &App, model: &Model, frame: Frame) {
+    let draw = app.draw();
+
+    match model.render_mode {
+        RenderMode::Lines => {
+            // Trails were already rendered during update, just copy them over.
+            let mut encoder = frame.command_encoder();
+            model.trails.reshaper.encode_render_pass(frame.texture_view(), &mut encoder);
+            return;
+        }
+        RenderMode::Rhombi => {
+            draw.background().color(Srgb::new(BG_COLOR, BG_COLOR, BG_COLOR));
+            for (&node, tile) in &model.tiles {
+                // Face the rhombus the same way as the cube face perpendicular to our travel.
+                let dir = tile.axis * 2;
+                draw_face(&draw, to_world(node), dir, shade(tile.color, FACE_SHADES[tile.axis]));
+            }
+        }
+        RenderMode::Cubes => {
+            draw.background().color(Srgb::new(BG_COLOR, BG_COLOR, BG_COLOR));
+            // Collect the cubes touched by visited nodes.
+            let mut cubes = HashMap::new();
+            for (&node, tile) in &model.tiles {
+                let center = if is_cube_center(node) {
+                    node
+                } else {
+                    // A corner belongs to three cubes. Take the one behind us.
+                    let back = (tile.axis * 2 + 3) % 6;
+                    (0..6).map(|i| wrap(step(node, back + i)))
+                        .find(|&n| is_cube_center(n))
+                        .unwrap()
+                };
+                cubes.insert(center, tile.color);
+            }
+            // Draw back to front so nearer cubes overlap farther ones.
+            let mut cubes: Vec<_> = cubes.into_iter().collect();
+            cubes.sort_by(|a, b| to_world(b.0).y.partial_cmp(&to_world(a.0).y).unwrap());
+            for (center, color) in cubes {
+                draw_cube(&draw, to_world(center), color);
+            }
+        }
     }
 
     draw.to_frame(app, &frame)
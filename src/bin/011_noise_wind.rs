@@ -1,73 +1,1028 @@
 // Inspired by: https://www.reddit.com/r/generative/comments/f6vlg5/how_to_generate_a_wind_field_using_perlin_noise/
 // And: https://codepen.io/Mamboleoo/pen/xxGEVXM
 
+use std::collections::VecDeque;
 use std::f32::consts::TAU;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::task::{self, Poll, Wake, Waker};
+use std::thread;
 
 use nannou::prelude::*;
-use nannou::noise::{NoiseFn, Perlin};
+use nannou::wgpu;
+use nannou::noise::{Fbm, MultiFractal, NoiseFn, OpenSimplex, RidgedMulti, Seedable, Value, Worley};
 
-const GRID_WIDTH: usize = 60;
-const GRID_HEIGHT: usize = 60;
+// The grid covers the window with square cells this many pixels wide.
+const CELL_SIZE: f32 = 15.0;
+// Half the distance, in cells, between the samples used to estimate the noise gradient.
+const CURL_EPSILON: f64 = 0.5;
+
+const STREAMLINE_SPACING: usize = 4;
+const STREAMLINE_STEPS: usize = 40;
+
+// Offsets for the two extra lookups used to warp the domain, chosen to be far from each other.
+const WARP_OFFSETS: [[f64; 2]; 2] = [[5.2, 1.3], [1.7, 9.2]];
+
+const PARTICLE_COUNT: usize = 1000;
+const MAX_PARTICLE_COUNT: usize = 20000;
+// In pixels per second.
+const PARTICLE_SPEED: f32 = 60.0;
+// In seconds.
+const PARTICLE_LIFETIME: f32 = 6.0;
+// How many past positions each particle remembers for its trail.
+const TRAIL_LENGTH: usize = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NoiseKind {
+    Perlin,
+    OpenSimplex,
+    Worley,
+    Value,
+    Fbm,
+    RidgedMulti,
+}
+
+impl NoiseKind {
+    fn next(self) -> Self {
+        match self {
+            NoiseKind::Perlin => NoiseKind::OpenSimplex,
+            NoiseKind::OpenSimplex => NoiseKind::Worley,
+            NoiseKind::Worley => NoiseKind::Value,
+            NoiseKind::Value => NoiseKind::Fbm,
+            NoiseKind::Fbm => NoiseKind::RidgedMulti,
+            NoiseKind::RidgedMulti => NoiseKind::Perlin,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct NoiseSettings {
+    kind: NoiseKind,
+    seed: u32,
+    // Only used by the fractal kinds.
+    octaves: usize,
+    lacunarity: f64,
+    persistence: f64,
+    // Scales grid cells and seconds into noise space.
+    frequency: f64,
+    time_scale: f64,
+    // How far to push lookups around by another layer of the same noise. Zero disables warping.
+    warp: f64,
+}
+
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        Self {
+            kind: NoiseKind::Perlin,
+            seed: 0,
+            octaves: 4,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            frequency: 0.02,
+            time_scale: 0.2,
+            warp: 0.0,
+        }
+    }
+}
+
+impl NoiseSettings {
+    fn build(self) -> NoiseField {
+        let source: Box<dyn NoiseFn<[f64; 3]>> = match self.kind {
+            // noise::Perlin is ambiguous with the surflet version, but a one octave Fbm is plain
+            // Perlin noise.
+            NoiseKind::Perlin => Box::new(Fbm::new().set_seed(self.seed).set_octaves(1)),
+            NoiseKind::OpenSimplex => Box::new(OpenSimplex::new().set_seed(self.seed)),
+            NoiseKind::Worley => Box::new(Worley::new().set_seed(self.seed)),
+            NoiseKind::Value => Box::new(Value::new().set_seed(self.seed)),
+            NoiseKind::Fbm => Box::new(
+                Fbm::new()
+                    .set_seed(self.seed)
+                    .set_octaves(self.octaves)
+                    .set_lacunarity(self.lacunarity)
+                    .set_persistence(self.persistence),
+            ),
+            NoiseKind::RidgedMulti => Box::new(
+                RidgedMulti::new()
+                    .set_seed(self.seed)
+                    .set_octaves(self.octaves)
+                    .set_lacunarity(self.lacunarity)
+                    .set_persistence(self.persistence),
+            ),
+        };
+
+        NoiseField {
+            settings: self,
+            source,
+        }
+    }
+}
+
+struct NoiseField {
+    settings: NoiseSettings,
+    source: Box<dyn NoiseFn<[f64; 3]>>,
+}
+
+impl NoiseField {
+    // Noise value in roughly [-1, 1] at grid coordinates (x, y) and time t.
+    fn get(&self, x: f64, y: f64, t: f64) -> f32 {
+        let settings = &self.settings;
+        let mut x = x * settings.frequency;
+        let mut y = y * settings.frequency;
+        let z = t * settings.time_scale;
+        if settings.warp != 0.0 {
+            let [ox, oy] = WARP_OFFSETS;
+            let qx = self.source.get([x + ox[0], y + ox[1], z]);
+            let qy = self.source.get([x + oy[0], y + oy[1], z]);
+            x += settings.warp * qx;
+            y += settings.warp * qy;
+        }
+        self.source.get([x, y, z]) as f32
+    }
+}
+
+struct Particle {
+    // Most recent position first.
+    trail: VecDeque<Vec2>,
+    age: f32,
+    lifetime: f32,
+}
+
+impl Particle {
+    fn new(rect: Rect, lifetime: f32) -> Self {
+        let pos = vec2(
+            random_range(rect.left(), rect.right()),
+            random_range(rect.bottom(), rect.top()),
+        );
+        let mut trail = VecDeque::with_capacity(TRAIL_LENGTH);
+        trail.push_front(pos);
+        Self {
+            trail,
+            age: 0.0,
+            // Stagger lifetimes so particles don't all respawn at once.
+            lifetime: lifetime * random_range(0.5, 1.5),
+        }
+    }
+
+    fn pos(&self) -> Vec2 {
+        self.trail[0]
+    }
+}
+
+// How the noise turns into wind.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FieldMode {
+    // The noise value picks the angle directly, and its magnitude.
+    Angle,
+    // The noise is a stream function and wind flows along its contours. This is divergence-free,
+    // so there are no sources or sinks for particles to pile into.
+    Curl,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DisplayMode {
+    Hidden,
+    // One line per cell, fading with magnitude.
+    Lines,
+    Arrows,
+    // Arrows scaled by magnitude.
+    ScaledArrows,
+    // Curves traced through the field from evenly spaced seeds.
+    Streamlines,
+    // Each cell's hue shows direction and brightness shows magnitude.
+    ColorWheel,
+}
+
+impl DisplayMode {
+    fn next(self) -> Self {
+        match self {
+            DisplayMode::Hidden => DisplayMode::Lines,
+            DisplayMode::Lines => DisplayMode::Arrows,
+            DisplayMode::Arrows => DisplayMode::ScaledArrows,
+            DisplayMode::ScaledArrows => DisplayMode::Streamlines,
+            DisplayMode::Streamlines => DisplayMode::ColorWheel,
+            DisplayMode::ColorWheel => DisplayMode::Hidden,
+        }
+    }
+}
+
+// Everything needed to compute a field.
+#[derive(Clone, Copy, Debug)]
+struct FieldRequest {
+    noise: NoiseSettings,
+    pinned_noise: Option<NoiseSettings>,
+    mode: FieldMode,
+    grid_width: usize,
+    grid_height: usize,
+    t: f64,
+}
+
+// A request for the worker thread, along with the back buffer to write into. It gets sent back
+// once the buffer is filled in.
+struct FieldJob {
+    request: FieldRequest,
+    field: Vec<Vec2>,
+}
+
+// Computes fields on a background thread so update never waits on noise evaluation.
+struct FieldWorker {
+    jobs: Sender<FieldJob>,
+    results: Receiver<FieldJob>,
+    // Only one job is in flight at a time.
+    busy: bool,
+}
+
+impl FieldWorker {
+    fn spawn() -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<FieldJob>();
+        let (result_sender, results) = mpsc::channel();
+        thread::spawn(move || {
+            // Fractal noise is costly to build, so hang on to it until the settings change.
+            let mut noise_cache = None;
+            let mut pinned_cache = None;
+            // Stops once the model drops its end of the channel.
+            for mut job in job_receiver {
+                let request = job.request;
+                let noise = cached_noise(&mut noise_cache, request.noise);
+                let pinned_noise = request.pinned_noise.map(|settings| cached_noise(&mut pinned_cache, settings));
+                job.field.clear();
+                job.field.resize(request.grid_width * request.grid_height, Vec2::ZERO);
+                compute_field(&mut job.field, request.grid_width, request.mode, noise, pinned_noise, request.t);
+                if result_sender.send(job).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            jobs,
+            results,
+            busy: false,
+        }
+    }
+
+    // Returns the last job if it's done. Never blocks.
+    fn receive(&mut self) -> Option<FieldJob> {
+        let job = self.results.try_recv().ok()?;
+        self.busy = false;
+        Some(job)
+    }
+
+    fn submit(&mut self, job: FieldJob) {
+        self.busy = self.jobs.send(job).is_ok();
+    }
+}
+
+fn cached_noise(cache: &mut Option<NoiseField>, settings: NoiseSettings) -> &NoiseField {
+    if !matches!(cache, Some(noise) if noise.settings == settings) {
+        *cache = Some(settings.build());
+    }
+    cache.as_ref().unwrap()
+}
+
+// Size of one cell in the GPU field buffer.
+const GPU_CELL_BYTES: usize = 2 * std::mem::size_of::<f32>();
+const GPU_WORKGROUP_SIZE: usize = 8;
+
+// Lets us check on a future without an async runtime. We poll every frame anyway.
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+// A field being computed on the GPU, waiting to be read back.
+struct PendingRead {
+    future: MapFuture,
+    request: FieldRequest,
+}
+
+// Computes fields in a compute shader. Results are read back asynchronously, so like the worker
+// thread, the field on screen may be a frame or two behind.
+struct GpuField {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    storage_buffer: wgpu::Buffer,
+    read_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    // How many cells the buffers can hold.
+    capacity: usize,
+    pending: Option<PendingRead>,
+}
+
+impl GpuField {
+    // Returns None if the device can't run compute shaders.
+    fn new(device: &wgpu::Device) -> Option<Self> {
+        if device.limits().max_storage_buffers_per_shader_stage == 0 {
+            return None;
+        }
+
+        let module = device.create_shader_module(&wgpu::include_wgsl!("../shaders/noise_wind_field.wgsl"));
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("noise wind field"),
+            entries: &[
+                buffer_entry(0, wgpu::BufferBindingType::Storage { read_only: false }),
+                buffer_entry(1, wgpu::BufferBindingType::Uniform),
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("noise wind field"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("noise wind field"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: "main",
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("noise wind uniforms"),
+            size: gpu_uniforms(&NoiseSettings::default(), FieldMode::Angle, 0, 0, 0.0).len() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let capacity = 1;
+        let (storage_buffer, read_buffer) = create_field_buffers(device, capacity);
+        let bind_group = create_field_bind_group(device, &bind_group_layout, &storage_buffer, &uniform_buffer);
+
+        Some(Self {
+            pipeline,
+            bind_group_layout,
+            uniform_buffer,
+            storage_buffer,
+            read_buffer,
+            bind_group,
+            capacity,
+            pending: None,
+        })
+    }
+
+    // Only gradient noise is implemented in the shader, and comparing stays on the CPU.
+    fn supports(request: &FieldRequest) -> bool {
+        let kind_supported = matches!(
+            request.noise.kind,
+            NoiseKind::Perlin | NoiseKind::Fbm | NoiseKind::RidgedMulti
+        );
+        kind_supported && request.pinned_noise.is_none()
+    }
+
+    fn dispatch(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, request: FieldRequest) {
+        let cells = request.grid_width * request.grid_height;
+        if cells > self.capacity {
+            self.capacity = cells;
+            let (storage_buffer, read_buffer) = create_field_buffers(device, cells);
+            self.bind_group = create_field_bind_group(device, &self.bind_group_layout, &storage_buffer, &self.uniform_buffer);
+            self.storage_buffer = storage_buffer;
+            self.read_buffer = read_buffer;
+        }
+
+        let uniforms = gpu_uniforms(&request.noise, request.mode, request.grid_width, request.grid_height, request.t);
+        queue.write_buffer(&self.uniform_buffer, 0, &uniforms);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("noise wind field"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("noise wind field"),
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            let groups_x = request.grid_width.div_ceil(GPU_WORKGROUP_SIZE);
+            let groups_y = request.grid_height.div_ceil(GPU_WORKGROUP_SIZE);
+            pass.dispatch(groups_x as u32, groups_y as u32, 1);
+        }
+        let size = (cells * GPU_CELL_BYTES) as wgpu::BufferAddress;
+        encoder.copy_buffer_to_buffer(&self.storage_buffer, 0, &self.read_buffer, 0, size);
+        queue.submit(Some(encoder.finish()));
+
+        let future = self.read_buffer.slice(..size).map_async(wgpu::MapMode::Read);
+        self.pending = Some(PendingRead {
+            future: Box::pin(future),
+            request,
+        });
+    }
+
+    // Forgets the last dispatched field if it hasn't been read yet, so it can't be swapped in after
+    // whatever it was computed for has changed.
+    fn cancel(&mut self) {
+        if self.pending.take().is_some() {
+            self.read_buffer.unmap();
+        }
+    }
+
+    // Copies the last dispatched field into out if it's ready. Never blocks.
+    fn try_read(&mut self, device: &wgpu::Device, out: &mut Vec<Vec2>) -> Option<FieldRequest> {
+        let pending = self.pending.as_mut()?;
+        device.poll(wgpu::Maintain::Poll);
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = task::Context::from_waker(&waker);
+        let result = match pending.future.as_mut().poll(&mut cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return None,
+        };
+        let request = self.pending.take().unwrap().request;
+        if result.is_err() {
+            return None;
+        }
+
+        let size = (request.grid_width * request.grid_height * GPU_CELL_BYTES) as wgpu::BufferAddress;
+        {
+            let bytes = self.read_buffer.slice(..size).get_mapped_range();
+            let float = |b: &[u8]| f32::from_ne_bytes(b.try_into().unwrap());
+            out.clear();
+            out.extend(bytes.chunks_exact(GPU_CELL_BYTES).map(|cell| vec2(float(&cell[..4]), float(&cell[4..]))));
+        }
+        self.read_buffer.unmap();
+
+        if request.mode == FieldMode::Curl {
+            normalize_field(out);
+        }
+        Some(request)
+    }
+}
+
+fn create_field_buffers(device: &wgpu::Device, cells: usize) -> (wgpu::Buffer, wgpu::Buffer) {
+    let size = (cells * GPU_CELL_BYTES) as wgpu::BufferAddress;
+    let storage_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("noise wind field"),
+        size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let read_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("noise wind field readback"),
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    (storage_buffer, read_buffer)
+}
+
+fn create_field_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    storage_buffer: &wgpu::Buffer,
+    uniform_buffer: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("noise wind field"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: storage_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+// Packs the `Uniforms` struct in noise_wind_field.wgsl.
+fn gpu_uniforms(noise: &NoiseSettings, mode: FieldMode, grid_width: usize, grid_height: usize, t: f64) -> Vec<u8> {
+    let field_mode: u32 = match mode {
+        FieldMode::Angle => 0,
+        FieldMode::Curl => 1,
+    };
+    let fractal: u32 = match noise.kind {
+        NoiseKind::Fbm => 1,
+        NoiseKind::RidgedMulti => 2,
+        _ => 0,
+    };
+    let words = [
+        (grid_width as u32).to_ne_bytes(),
+        (grid_height as u32).to_ne_bytes(),
+        field_mode.to_ne_bytes(),
+        fractal.to_ne_bytes(),
+        (noise.octaves as u32).to_ne_bytes(),
+        noise.seed.to_ne_bytes(),
+        (noise.frequency as f32).to_ne_bytes(),
+        ((t * noise.time_scale) as f32).to_ne_bytes(),
+        (noise.lacunarity as f32).to_ne_bytes(),
+        (noise.persistence as f32).to_ne_bytes(),
+        (noise.warp as f32).to_ne_bytes(),
+        (CURL_EPSILON as f32).to_ne_bytes(),
+    ];
+    words.concat()
+}
 
 struct Model {
-    noise_grid: Vec<f32>,
-    noise: Perlin,
+    // Wind velocity for each cell, row-major from the top left. Lengths are in [0, 1].
+    field: Vec<Vec2>,
+    // The next field gets computed into this one, then they're swapped.
+    back_buffer: Vec<Vec2>,
+    grid_width: usize,
+    grid_height: usize,
+    field_mode: FieldMode,
+    noise: NoiseSettings,
+    // When comparing, the left half of the grid is computed from these settings instead.
+    pinned_noise: Option<NoiseSettings>,
+
+    worker: FieldWorker,
+    // None if the GPU can't run compute shaders.
+    gpu: Option<GpuField>,
+    use_gpu: bool,
+
+    display_mode: DisplayMode,
+    show_particles: bool,
+    particles: Vec<Particle>,
+    particle_count: usize,
+    particle_speed: f32,
+    particle_lifetime: f32,
+}
+
+impl Model {
+    fn cell_center(&self, rect: Rect, i: usize, j: usize) -> Vec2 {
+        vec2(
+            rect.left() + (i as f32 + 0.5) * CELL_SIZE,
+            rect.top() - (j as f32 + 0.5) * CELL_SIZE,
+        )
+    }
+
+    // Resizes the grid to cover the window.
+    fn fit_grid(&mut self, rect: Rect) {
+        let grid_width = (rect.w() / CELL_SIZE).ceil().max(1.0) as usize;
+        let grid_height = (rect.h() / CELL_SIZE).ceil().max(1.0) as usize;
+        if grid_width != self.grid_width || grid_height != self.grid_height {
+            self.grid_width = grid_width;
+            self.grid_height = grid_height;
+            self.field = vec![Vec2::ZERO; grid_width * grid_height];
+        }
+    }
+
+    // The wind velocity at pos, blended between the four closest cells.
+    fn sample_velocity(&self, rect: Rect, pos: Vec2) -> Vec2 {
+        // Fractional cell coordinates, measured from the center of the top left cell.
+        let fx = clamp((pos.x - rect.left()) / CELL_SIZE - 0.5, 0.0, (self.grid_width - 1) as f32);
+        let fy = clamp((rect.top() - pos.y) / CELL_SIZE - 0.5, 0.0, (self.grid_height - 1) as f32);
+        let (i0, j0) = (fx.floor() as usize, fy.floor() as usize);
+        let (i1, j1) = ((i0 + 1).min(self.grid_width - 1), (j0 + 1).min(self.grid_height - 1));
+        let (tx, ty) = (fx - i0 as f32, fy - j0 as f32);
+
+        // Blend vectors rather than angles so we don't spin the wrong way around.
+        let vel = |i: usize, j: usize| self.field[j * self.grid_width + i];
+        let top = vel(i0, j0).lerp(vel(i1, j0), tx);
+        let bottom = vel(i0, j1).lerp(vel(i1, j1), tx);
+        top.lerp(bottom, ty)
+    }
+
+    fn set_noise(&mut self, settings: NoiseSettings) {
+        println!("{:?}", settings);
+        self.noise = settings;
+    }
+
+    // Swaps in a finished field, unless the grid was resized since it was requested.
+    fn swap_field(&mut self, request: FieldRequest) {
+        if request.grid_width == self.grid_width && request.grid_height == self.grid_height {
+            std::mem::swap(&mut self.field, &mut self.back_buffer);
+        }
+    }
+
+    fn resize_particles(&mut self, rect: Rect) {
+        let lifetime = self.particle_lifetime;
+        self.particles.truncate(self.particle_count);
+        while self.particles.len() < self.particle_count {
+            self.particles.push(Particle::new(rect, lifetime));
+        }
+    }
+}
+
+fn value_to_angle(value: f32) -> f32 {
+    map_range(value, -1.0, 1.0, 0.0, TAU)
+}
+
+// Computes the velocity of every cell.
+fn compute_field(
+    field: &mut [Vec2],
+    grid_width: usize,
+    mode: FieldMode,
+    noise: &NoiseField,
+    pinned: Option<&NoiseField>,
+    t: f64,
+) {
+    for (j, row) in field.chunks_mut(grid_width).enumerate() {
+        for (i, vel) in row.iter_mut().enumerate() {
+            let noise = match pinned {
+                Some(pinned) if i < grid_width / 2 => pinned,
+                _ => noise,
+            };
+            let (x, y) = (i as f64, j as f64);
+            *vel = match mode {
+                FieldMode::Angle => {
+                    let value = noise.get(x, y, t);
+                    let angle = value_to_angle(value);
+                    vec2(angle.cos(), angle.sin()) * map_range(value, -1.0, 1.0, 0.0, 1.0)
+                }
+                FieldMode::Curl => {
+                    let e = CURL_EPSILON;
+                    let dx = (noise.get(x + e, y, t) - noise.get(x - e, y, t)) / (2.0 * e) as f32;
+                    let dy = (noise.get(x, y + e, t) - noise.get(x, y - e, t)) / (2.0 * e) as f32;
+                    // Grid rows run down the screen, so flip y to get the rotated gradient in
+                    // screen space.
+                    vec2(-dy, -dx)
+                }
+            };
+        }
+    }
+
+    if mode == FieldMode::Curl {
+        normalize_field(field);
+    }
+}
+
+// Gradients have no fixed range, so scale the strongest wind to 1.
+fn normalize_field(field: &mut [Vec2]) {
+    let max = field.iter().fold(0.0f32, |max, vel| max.max(vel.length()));
+    if max > 0.0 {
+        for vel in field.iter_mut() {
+            *vel /= max;
+        }
+    }
 }
 
 fn model(app: &App) -> Model {
-    let _window = app.new_window()
+    let window_id = app.new_window()
         .title("Noise Wind")
+        .event(event)
         .view(view)
         .build()
         .unwrap();
+    let window = app.window(window_id).unwrap();
+
+    let gpu = GpuField::new(window.device());
+    if gpu.is_none() {
+        println!("Compute shaders aren't available, computing the field on the CPU");
+    }
+
+    let rect = app.window_rect();
+    let mut model = Model {
+        field: Vec::new(),
+        back_buffer: Vec::new(),
+        grid_width: 0,
+        grid_height: 0,
+        field_mode: FieldMode::Angle,
+        noise: NoiseSettings::default(),
+        pinned_noise: None,
+
+        worker: FieldWorker::spawn(),
+        gpu,
+        use_gpu: false,
+
+        display_mode: DisplayMode::Lines,
+        show_particles: true,
+        particles: (0..PARTICLE_COUNT).map(|_| Particle::new(rect, PARTICLE_LIFETIME)).collect(),
+        particle_count: PARTICLE_COUNT,
+        particle_speed: PARTICLE_SPEED,
+        particle_lifetime: PARTICLE_LIFETIME,
+    };
+    model.fit_grid(rect);
+    model
+}
+
+fn event(app: &App, model: &mut Model, event: WindowEvent) {
+    // Hold shift to turn noise parameters down instead of up.
+    let scale = if app.keys.mods.shift() { 1.0 / 1.25 } else { 1.25 };
+    let mut settings = model.noise;
 
-    let noise = Perlin::new();
-    let grid_size = GRID_WIDTH * GRID_HEIGHT;
-    Model {
-        noise_grid: vec![1.0; grid_size],
-        noise,
+    match event {
+        WindowEvent::KeyPressed(Key::N) => {
+            settings.kind = settings.kind.next();
+            model.set_noise(settings);
+        }
+        WindowEvent::KeyPressed(Key::S) => {
+            settings.seed = random();
+            model.set_noise(settings);
+        }
+        WindowEvent::KeyPressed(Key::O) => {
+            settings.octaves = if app.keys.mods.shift() {
+                settings.octaves.saturating_sub(1).max(1)
+            } else {
+                (settings.octaves + 1).min(Fbm::MAX_OCTAVES)
+            };
+            model.set_noise(settings);
+        }
+        WindowEvent::KeyPressed(Key::L) => {
+            settings.lacunarity *= scale;
+            model.set_noise(settings);
+        }
+        WindowEvent::KeyPressed(Key::G) => {
+            settings.persistence = clamp(settings.persistence * scale, 0.05, 1.0);
+            model.set_noise(settings);
+        }
+        WindowEvent::KeyPressed(Key::F) => {
+            settings.frequency *= scale;
+            model.set_noise(settings);
+        }
+        WindowEvent::KeyPressed(Key::T) => {
+            settings.time_scale *= scale;
+            model.set_noise(settings);
+        }
+        WindowEvent::KeyPressed(Key::W) => {
+            settings.warp = if app.keys.mods.shift() {
+                (settings.warp - 0.25).max(0.0)
+            } else {
+                settings.warp + 0.25
+            };
+            model.set_noise(settings);
+        }
+        WindowEvent::KeyPressed(Key::C) => {
+            // Pin the current field to the left half of the screen so we can tweak the right.
+            model.pinned_noise = match model.pinned_noise {
+                Some(_) => None,
+                None => Some(settings),
+            };
+        }
+        WindowEvent::KeyPressed(Key::U) => {
+            model.use_gpu = !model.use_gpu && model.gpu.is_some();
+            println!("Compute on GPU: {}", model.use_gpu);
+            if model.use_gpu {
+                println!("(Perlin, Fbm and RidgedMulti only, and not while comparing. Others stay on the CPU)");
+            }
+        }
+        WindowEvent::KeyPressed(Key::A) => {
+            model.display_mode = model.display_mode.next();
+            println!("Display mode: {:?}", model.display_mode);
+        }
+        WindowEvent::KeyPressed(Key::V) => {
+            model.field_mode = match model.field_mode {
+                FieldMode::Angle => FieldMode::Curl,
+                FieldMode::Curl => FieldMode::Angle,
+            };
+            if let Some(gpu) = model.gpu.as_mut() {
+                gpu.cancel();
+            }
+            println!("Field mode: {:?}", model.field_mode);
+        }
+        WindowEvent::KeyPressed(Key::P) => {
+            model.show_particles = !model.show_particles;
+        }
+        WindowEvent::KeyPressed(Key::Up) => {
+            model.particle_count = (model.particle_count * 2).min(MAX_PARTICLE_COUNT);
+            model.resize_particles(app.window_rect());
+            println!("Particles: {}", model.particle_count);
+        }
+        WindowEvent::KeyPressed(Key::Down) => {
+            model.particle_count = (model.particle_count / 2).max(1);
+            model.resize_particles(app.window_rect());
+            println!("Particles: {}", model.particle_count);
+        }
+        WindowEvent::KeyPressed(Key::Right) => {
+            model.particle_speed *= 1.25;
+            println!("Particle speed: {:.1}", model.particle_speed);
+        }
+        WindowEvent::KeyPressed(Key::Left) => {
+            model.particle_speed /= 1.25;
+            println!("Particle speed: {:.1}", model.particle_speed);
+        }
+        WindowEvent::KeyPressed(Key::RBracket) => {
+            model.particle_lifetime *= 1.25;
+            println!("Particle lifetime: {:.1}s", model.particle_lifetime);
+        }
+        WindowEvent::KeyPressed(Key::LBracket) => {
+            model.particle_lifetime /= 1.25;
+            println!("Particle lifetime: {:.1}s", model.particle_lifetime);
+        }
+        _ => {}
+    }
+}
+
+fn update(app: &App, model: &mut Model, update: Update) {
+    let rect = app.window_rect();
+    model.fit_grid(rect);
+    update_field(app, model);
+
+    // Advect particles through the field.
+    let dt = update.since_last.as_secs_f32();
+    for i in 0..model.particles.len() {
+        let mut vel = model.sample_velocity(rect, model.particles[i].pos());
+        if model.field_mode == FieldMode::Angle {
+            // Magnitude here is a visual thing, so move at a steady pace.
+            vel = vel.normalize_or_zero();
+        }
+        let lifetime = model.particle_lifetime;
+        let particle = &mut model.particles[i];
+
+        let pos = particle.pos() + vel * model.particle_speed * dt;
+        particle.age += dt;
+        if particle.age > particle.lifetime || !rect.contains(pos) {
+            *particle = Particle::new(rect, lifetime);
+            continue;
+        }
+
+        particle.trail.push_front(pos);
+        particle.trail.truncate(TRAIL_LENGTH);
     }
 }
 
-fn update(app: &App, model: &mut Model, _update: Update) {
-    for (j, row) in model.noise_grid.chunks_mut(GRID_WIDTH).enumerate() {
-        for (i, value) in row.iter_mut().enumerate() {
-            *value = model.noise.get([i as f64 * 0.02, j as f64 * 0.02, app.time as f64 * 0.2]) as f32;
+// Swaps in the latest finished field and kicks off the next one, on the GPU when possible or the
+// worker thread otherwise. The field on screen lags a little behind, but we never wait for it.
+fn update_field(app: &App, model: &mut Model) {
+    let request = FieldRequest {
+        noise: model.noise,
+        pinned_noise: model.pinned_noise,
+        mode: model.field_mode,
+        grid_width: model.grid_width,
+        grid_height: model.grid_height,
+        t: app.time as f64,
+    };
+
+    let use_gpu = model.use_gpu && model.gpu.is_some() && GpuField::supports(&request);
+
+    // Keep collecting CPU results even while using the GPU so the worker is free again, but only
+    // show them on the CPU path. One finishing after the switch would be older than the GPU's.
+    if let Some(job) = model.worker.receive() {
+        model.back_buffer = job.field;
+        if !use_gpu {
+            model.swap_field(job.request);
+        }
+    }
+
+    let window = app.main_window();
+    match model.gpu.as_mut() {
+        Some(gpu) if use_gpu => {
+            if let Some(done) = gpu.try_read(window.device(), &mut model.back_buffer) {
+                model.swap_field(done);
+            }
+            // Borrow again since swapping needed all of the model.
+            let gpu = model.gpu.as_mut().unwrap();
+            if gpu.pending.is_none() {
+                gpu.dispatch(window.device(), window.queue(), request);
+            }
+        }
+        gpu => {
+            // Anything the GPU was still working on is from before we switched away from it.
+            if let Some(gpu) = gpu {
+                gpu.cancel();
+            }
+            if !model.worker.busy {
+                let field = std::mem::take(&mut model.back_buffer);
+                model.worker.submit(FieldJob {
+                    request,
+                    field,
+                });
+            }
         }
     }
 }
 
+// Traces a streamline from seed in both directions, with the backward half reversed so the points
+// run in order.
+fn trace_streamline(model: &Model, rect: Rect, seed: Vec2) -> Vec<Vec2> {
+    let step_size = CELL_SIZE * 0.5;
+    let trace = |sign: f32| {
+        let mut points = Vec::with_capacity(STREAMLINE_STEPS);
+        let mut pos = seed;
+        for _ in 0..STREAMLINE_STEPS {
+            // Midpoint method, so curves don't spiral outward as much as plain Euler steps.
+            let dir = model.sample_velocity(rect, pos).normalize_or_zero() * sign;
+            let mid = pos + dir * step_size * 0.5;
+            let dir = model.sample_velocity(rect, mid).normalize_or_zero() * sign;
+            if dir == Vec2::ZERO {
+                break;
+            }
+            pos += dir * step_size;
+            if !rect.contains(pos) {
+                break;
+            }
+            points.push(pos);
+        }
+        points
+    };
+
+    let mut points = trace(-1.0);
+    points.reverse();
+    points.push(seed);
+    points.extend(trace(1.0));
+    points
+}
+
 fn view(app: &App, model: &Model, frame: Frame) {
-    let (w, h) = app.window_rect().w_h();
-    let cell_width = w / GRID_WIDTH as f32;
-    let cell_height = h / GRID_HEIGHT as f32;
-    let left = -w / 2.0 + cell_width / 2.0;
-    let top = h / 2.0 - cell_height / 2.0;
+    let rect = app.window_rect();
 
     let draw = app.draw();
 
     draw.background()
         .color(BLACK);
 
-    for (j, row) in model.noise_grid.chunks(GRID_WIDTH).enumerate() {
-        for (i, value) in row.iter().enumerate() {
-            let cell_center = Vec2::new(left + i as f32 * cell_width, top - j as f32 * cell_height);
-            let start = -Vec2::new(cell_width * 0.4, 0.0);
-            let end = Vec2::new(cell_width * 0.4, 0.0);
-
-            let alpha = map_range(*value, -1.0, 1.0, 0.0, 1.0);
-            let color = Rgba::new(1.0, 1.0, 1.0, alpha);
-            let angle = map_range(*value, -1.0, 1.0, 0.0, TAU);
-            let draw = draw.xy(cell_center);
-            draw.line()
-                .stroke_weight(1.5)
-                .start(start)
-                .end(end)
-                .rotate(angle)
-                .color(color);
+    let cells = model.field.iter().enumerate().map(|(k, &vel)| {
+        let (i, j) = (k % model.grid_width, k / model.grid_width);
+        (model.cell_center(rect, i, j), vel)
+    });
+    match model.display_mode {
+        DisplayMode::Hidden => {}
+        DisplayMode::Lines => {
+            for (cell_center, vel) in cells {
+                let start = -Vec2::new(CELL_SIZE * 0.4, 0.0);
+                let end = Vec2::new(CELL_SIZE * 0.4, 0.0);
+
+                let color = Rgba::new(1.0, 1.0, 1.0, vel.length());
+                let draw = draw.xy(cell_center);
+                draw.line()
+                    .stroke_weight(1.5)
+                    .start(start)
+                    .end(end)
+                    .rotate(vel.angle())
+                    .color(color);
+            }
+        }
+        DisplayMode::Arrows | DisplayMode::ScaledArrows => {
+            for (cell_center, vel) in cells {
+                let length = if model.display_mode == DisplayMode::ScaledArrows {
+                    CELL_SIZE * 0.9 * vel.length()
+                } else {
+                    CELL_SIZE * 0.8
+                };
+                if length < 1.0 {
+                    continue;
+                }
+                let offset = vel.normalize_or_zero() * length / 2.0;
+                draw.arrow()
+                    .weight(1.0)
+                    .head_length(CELL_SIZE * 0.25)
+                    .head_width(CELL_SIZE * 0.15)
+                    .start(cell_center - offset)
+                    .end(cell_center + offset)
+                    .color(Rgba::new(1.0, 1.0, 1.0, 0.3 + 0.7 * vel.length()));
+            }
+        }
+        DisplayMode::Streamlines => {
+            for j in (STREAMLINE_SPACING / 2..model.grid_height).step_by(STREAMLINE_SPACING) {
+                for i in (STREAMLINE_SPACING / 2..model.grid_width).step_by(STREAMLINE_SPACING) {
+                    let points = trace_streamline(model, rect, model.cell_center(rect, i, j));
+                    if points.len() < 2 {
+                        continue;
+                    }
+                    // Fade out toward both ends.
+                    let len = (points.len() - 1) as f32;
+                    let points = points.into_iter().enumerate().map(|(k, pos)| {
+                        let alpha = 1.0 - (k as f32 / len * 2.0 - 1.0).abs();
+                        (pos, Rgba::new(1.0, 1.0, 1.0, alpha * 0.8))
+                    });
+                    draw.polyline()
+                        .stroke_weight(1.0)
+                        .points_colored(points);
+                }
+            }
+        }
+        DisplayMode::ColorWheel => {
+            for (cell_center, vel) in cells {
+                let hue = (vel.angle() / TAU).rem_euclid(1.0);
+                draw.rect()
+                    .xy(cell_center)
+                    .w_h(CELL_SIZE, CELL_SIZE)
+                    .hsv(hue, 0.8, vel.length());
+            }
         }
     }
 
+    if model.show_particles {
+        for particle in &model.particles {
+            if particle.trail.len() < 2 {
+                continue;
+            }
+            // Fade in when spawning and out when dying, and fade along the trail.
+            let life = particle.age / particle.lifetime;
+            let fade = (life * 10.0).min(1.0) * ((1.0 - life) * 10.0).min(1.0);
+            let len = particle.trail.len() as f32;
+            let points = particle.trail.iter().enumerate().map(|(k, &pos)| {
+                let alpha = fade * (1.0 - k as f32 / len);
+                (pos, Rgba::new(0.6, 0.85, 1.0, alpha))
+            });
+            draw.polyline()
+                .stroke_weight(1.0)
+                .points_colored(points);
+        }
+    }
+
+    if model.pinned_noise.is_some() {
+        // Mark where the pinned field ends.
+        let x = rect.left() + (model.grid_width / 2) as f32 * CELL_SIZE;
+        draw.line()
+            .stroke_weight(2.0)
+            .start(vec2(x, rect.bottom()))
+            .end(vec2(x, rect.top()))
+            .color(RED);
+    }
+
     draw.to_frame(app, &frame)
         .unwrap();
 }
@@ -0,0 +1,519 @@
+// Based on the Processing Flocking example: https://processing.org/examples/flocking.html
+// The same rules as 00x_extended_boids, but flying around inside a box in 3D.
+
+use nannou::prelude::*;
+
+const WIDTH: f32 = 800.0;
+const HEIGHT: f32 = 600.0;
+
+const INITIAL_BOID_COUNT: u32 = 300;
+const BOID_RADIUS: f32 = 4.0;
+const MAX_SPEED: f32 = 2.0;
+const MAX_FORCE: f32 = 0.03;
+const SEPARATION_DIST: f32 = 25.0;
+const NEIGHBOR_DIST: f32 = 50.0;
+const REPEL_DIST: f32 = 50.0;
+
+// The boids live in a cube this wide, centered on the origin.
+const BOX_SIZE: f32 = 400.0;
+// In soft walls mode, boids start turning back this far from a wall.
+const WALL_MARGIN: f32 = 50.0;
+const WALL_FORCE: f32 = 0.1;
+
+// Distance from the camera to the projection plane, in pixels. Bigger is a narrower field of view.
+const FOCAL_LENGTH: f32 = 700.0;
+const CAMERA_DISTANCE: f32 = 900.0;
+const MIN_CAMERA_DISTANCE: f32 = 300.0;
+const MAX_CAMERA_DISTANCE: f32 = 2000.0;
+// Radians per pixel of mouse movement.
+const ORBIT_SPEED: f32 = 0.01;
+// Anything closer to the camera than this isn't drawn.
+const NEAR_PLANE: f32 = 10.0;
+
+// Faces are lit from this direction, with some ambient light so the dark sides aren't black.
+const LIGHT_DIR: [f32; 3] = [0.3, 0.8, 0.5];
+const AMBIENT_LIGHT: f32 = 0.35;
+// How bright the farthest corner of the box is compared to the nearest.
+const FAR_BRIGHTNESS: f32 = 0.3;
+
+const TWO_PI: f32 = 2.0 * std::f32::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BoundaryMode {
+    // Leave one side of the box and come back in on the other.
+    Wrap,
+    // Steer away from the walls when getting close.
+    SoftWalls,
+}
+
+impl BoundaryMode {
+    fn next(self) -> Self {
+        match self {
+            BoundaryMode::Wrap => BoundaryMode::SoftWalls,
+            BoundaryMode::SoftWalls => BoundaryMode::Wrap,
+        }
+    }
+
+    // The shortest vector from one position to another. When wrapping, that may cross the seam.
+    fn offset(self, from: Vec3, to: Vec3) -> Vec3 {
+        let offset = to - from;
+        match self {
+            BoundaryMode::Wrap => offset - (offset / BOX_SIZE).round() * BOX_SIZE,
+            BoundaryMode::SoftWalls => offset,
+        }
+    }
+}
+
+struct Boid {
+    pos: Vec3,
+    vel: Vec3,
+}
+
+impl Boid {
+    fn new(pos: Vec3) -> Self {
+        // Pick a uniformly random direction on the sphere.
+        let angle = random_range(0.0, TWO_PI);
+        let z = random_range(-1.0, 1.0);
+        let r = (1.0 - z * z).sqrt();
+        Self {
+            pos,
+            vel: vec3(r * angle.cos(), r * angle.sin(), z),
+        }
+    }
+
+    // The four corners of a tetrahedron pointing along our velocity. The tip comes first.
+    fn corners(&self) -> [Vec3; 4] {
+        let forward = self.vel.normalize_or_zero();
+        let forward = if forward == Vec3::ZERO { Vec3::X } else { forward };
+        // Any vector not parallel to forward works for building the rest of the frame.
+        let other = if forward.y.abs() < 0.9 { Vec3::Y } else { Vec3::X };
+        let side = forward.cross(other).normalize();
+        let up = side.cross(forward);
+
+        let base_center = self.pos - forward * 2.0 * BOID_RADIUS;
+        let base = |angle: f32| base_center + (side * angle.cos() + up * angle.sin()) * BOID_RADIUS;
+        [
+            self.pos + forward * 2.0 * BOID_RADIUS,
+            base(0.0),
+            base(TWO_PI / 3.0),
+            base(2.0 * TWO_PI / 3.0),
+        ]
+    }
+}
+
+struct Repel {
+    pos: Vec3,
+}
+
+// Looks at the center of the box from a point on a sphere around it.
+struct OrbitCamera {
+    // Rotation around the vertical axis, in radians.
+    yaw: f32,
+    // Angle above the horizon, in radians.
+    pitch: f32,
+    distance: f32,
+}
+
+impl OrbitCamera {
+    fn eye(&self) -> Vec3 {
+        vec3(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        ) * self.distance
+    }
+
+    // Right, up and forward vectors of the camera.
+    fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let forward = -self.eye().normalize();
+        let right = forward.cross(Vec3::Y).normalize();
+        let up = right.cross(forward);
+        (right, up, forward)
+    }
+
+    // Screen position and depth of a point, or None if it's behind the camera.
+    fn project(&self, p: Vec3) -> Option<(Vec2, f32)> {
+        let (right, up, forward) = self.basis();
+        let rel = p - self.eye();
+        let depth = rel.dot(forward);
+        if depth < NEAR_PLANE {
+            return None;
+        }
+        let scale = FOCAL_LENGTH / depth;
+        Some((vec2(rel.dot(right), rel.dot(up)) * scale, depth))
+    }
+
+    // The point at the given depth that projects to a screen position.
+    fn unproject(&self, screen: Vec2, depth: f32) -> Vec3 {
+        let (right, up, forward) = self.basis();
+        let scale = depth / FOCAL_LENGTH;
+        self.eye() + forward * depth + (right * screen.x + up * screen.y) * scale
+    }
+
+    fn orbit(&mut self, delta: Vec2) {
+        self.yaw -= delta.x * ORBIT_SPEED;
+        // Stop short of the poles so the camera never flips over.
+        let limit = PI / 2.0 - 0.05;
+        self.pitch = clamp(self.pitch - delta.y * ORBIT_SPEED, -limit, limit);
+    }
+
+    fn zoom(&mut self, amount: f32) {
+        self.distance = clamp(self.distance * (1.0 - amount * 0.1), MIN_CAMERA_DISTANCE, MAX_CAMERA_DISTANCE);
+    }
+}
+
+struct Model {
+    boids: Vec<Boid>,
+    repels: Vec<Repel>,
+    boundary_mode: BoundaryMode,
+
+    camera: OrbitCamera,
+    // Where the mouse was last frame while dragging.
+    last_mouse: Option<Vec2>,
+
+    enable_separation: bool,
+    enable_alignment: bool,
+    enable_cohesion: bool,
+    enable_repulsion: bool,
+
+    separation_factor: f32,
+    alignment_factor: f32,
+    cohesion_factor: f32,
+    repulsion_factor: f32,
+}
+
+impl Model {
+    fn new() -> Self {
+        Self {
+            boids: (0..INITIAL_BOID_COUNT).map(|_| Boid::new(Vec3::ZERO)).collect(),
+            repels: Vec::new(),
+            boundary_mode: BoundaryMode::Wrap,
+
+            camera: OrbitCamera {
+                yaw: 0.5,
+                pitch: 0.3,
+                distance: CAMERA_DISTANCE,
+            },
+            last_mouse: None,
+
+            enable_separation: true,
+            enable_alignment: true,
+            enable_cohesion: true,
+            enable_repulsion: true,
+
+            separation_factor: 1.5,
+            alignment_factor: 1.0,
+            cohesion_factor: 1.0,
+            repulsion_factor: 1.5,
+        }
+    }
+}
+
+fn model(app: &App) -> Model {
+    let window_builder = nannou::winit::window::WindowBuilder::new()
+        .with_resizable(false);
+    let _window = app.new_window()
+        .window(window_builder)
+        .size_pixels(WIDTH as u32, HEIGHT as u32)
+        .title("3D Boids")
+        .event(event)
+        .view(view)
+        .build()
+        .unwrap();
+
+    Model::new()
+}
+
+fn event(app: &App, model: &mut Model, event: WindowEvent) {
+    match event {
+        WindowEvent::MousePressed(MouseButton::Right) => {
+            // Place the node on the plane through the center of the box, facing the camera.
+            let pos = model.camera.unproject(app.mouse.position(), model.camera.distance);
+            model.repels.push(Repel { pos });
+        }
+        WindowEvent::MouseWheel(delta, _) => {
+            let amount = match delta {
+                MouseScrollDelta::LineDelta(_, y) => y,
+                MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 50.0,
+            };
+            model.camera.zoom(amount);
+        }
+        WindowEvent::KeyPressed(Key::Space) => {
+            model.boids.extend((0..10).map(|_| Boid::new(Vec3::ZERO)));
+        }
+        WindowEvent::KeyPressed(Key::B) => {
+            model.boundary_mode = model.boundary_mode.next();
+            println!("Boundary mode: {:?}", model.boundary_mode);
+        }
+        WindowEvent::KeyPressed(Key::R) => {
+            model.boids = (0..INITIAL_BOID_COUNT).map(|_| Boid::new(Vec3::ZERO)).collect();
+            model.repels = Vec::new();
+        }
+        _ => {}
+    }
+}
+
+fn update(app: &App, model: &mut Model, _update: Update) {
+    // Drag with the left mouse button to orbit.
+    if app.mouse.buttons.left().is_down() {
+        let mouse = app.mouse.position();
+        if let Some(last) = model.last_mouse {
+            model.camera.orbit(mouse - last);
+        }
+        model.last_mouse = Some(mouse);
+    } else {
+        model.last_mouse = None;
+    }
+
+    // Collect forces and apply them after computing them.
+    let mut accels = vec![Vec3::ZERO; model.boids.len()];
+
+    if model.enable_separation && model.separation_factor > 0.0 {
+        for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
+            let separation = {
+                // Try to steer away from nearby boids.
+                let desired_separation = SEPARATION_DIST;
+
+                let mut steer = Vec3::ZERO;
+                let mut count = 0;
+
+                // Check if we're too close to all other boids.
+                for other in &model.boids {
+                    let offset = model.boundary_mode.offset(boid.pos, other.pos);
+                    let dist = offset.length();
+
+                    // If we're too close, modify our steering vector.
+                    if dist > 0.0 && dist < desired_separation {
+                        let diff = -offset.normalize() / dist;
+                        steer += diff;
+                        count += 1;
+                    }
+                }
+
+                // Average out the steering.
+                if count > 0 {
+                    steer /= count as f32;
+                }
+
+                if steer != Vec3::ZERO {
+                    steer = steer.clamp_length(MAX_SPEED, MAX_SPEED);
+                    steer -= boid.vel;
+                    steer = steer.clamp_length_max(MAX_FORCE);
+                }
+
+                steer
+            };
+            *accel += separation * model.separation_factor;
+        }
+    }
+
+    if model.enable_alignment && model.alignment_factor > 0.0 {
+        for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
+            let alignment = {
+                // Try to align with nearby boids.
+                let neighbor_dist = NEIGHBOR_DIST;
+
+                let mut sum = Vec3::ZERO;
+                let mut count = 0;
+
+                for other in &model.boids {
+                    let dist = model.boundary_mode.offset(boid.pos, other.pos).length();
+                    if dist > 0.0 && dist < neighbor_dist {
+                        sum += other.vel;
+                        count += 1;
+                    }
+                }
+
+                if count > 0 {
+                    let avg_vel = sum / count as f32;
+                    let desired_vel = avg_vel.clamp_length(MAX_SPEED, MAX_SPEED);
+                    (desired_vel - boid.vel).clamp_length_max(MAX_FORCE)
+                } else {
+                    Vec3::ZERO
+                }
+            };
+            *accel += alignment * model.alignment_factor;
+        }
+    }
+
+    if model.enable_cohesion && model.cohesion_factor > 0.0 {
+        for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
+            let cohesion = {
+                // Try to move to the center of nearby boids.
+                let neighbor_dist = NEIGHBOR_DIST;
+
+                let mut sum = Vec3::ZERO;
+                let mut count = 0;
+
+                for other in &model.boids {
+                    let offset = model.boundary_mode.offset(boid.pos, other.pos);
+                    let dist = offset.length();
+                    if dist > 0.0 && dist < neighbor_dist {
+                        // Use the neighbor's position on our side of the seam.
+                        sum += boid.pos + offset;
+                        count += 1;
+                    }
+                }
+
+                if count > 0 {
+                    let avg_pos = sum / count as f32;
+                    let desired = (avg_pos - boid.pos).clamp_length(MAX_SPEED, MAX_SPEED);
+                    (desired - boid.vel).clamp_length_max(MAX_FORCE)
+                } else {
+                    Vec3::ZERO
+                }
+            };
+            *accel += cohesion * model.cohesion_factor;
+        }
+    }
+
+    if model.enable_repulsion && model.repulsion_factor > 0.0 {
+        for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
+            let repulsion = {
+                // Try to move away from repel nodes.
+                let repel_dist = REPEL_DIST;
+
+                let mut sum = Vec3::ZERO;
+                let mut count = 0;
+
+                for repel in &model.repels {
+                    let dist = boid.pos.distance(repel.pos);
+                    if dist > 0.0 && dist < repel_dist {
+                        sum += repel.pos;
+                        count += 1;
+                    }
+                }
+
+                if count > 0 {
+                    let avg_pos = sum / count as f32;
+                    // Move away from the average position.
+                    let desired = -(avg_pos - boid.pos).clamp_length(MAX_SPEED, MAX_SPEED);
+                    (desired - boid.vel).clamp_length_max(MAX_FORCE)
+                } else {
+                    Vec3::ZERO
+                }
+            };
+            *accel += repulsion * model.repulsion_factor;
+        }
+    }
+
+    let half = BOX_SIZE / 2.0;
+    if model.boundary_mode == BoundaryMode::SoftWalls {
+        for (boid, accel) in model.boids.iter().zip(accels.iter_mut()) {
+            // Push back harder the closer we get to each wall.
+            let push = |coord: f32| {
+                let near_min = (WALL_MARGIN - (coord + half)).max(0.0) / WALL_MARGIN;
+                let near_max = (WALL_MARGIN - (half - coord)).max(0.0) / WALL_MARGIN;
+                near_min - near_max
+            };
+            *accel += vec3(push(boid.pos.x), push(boid.pos.y), push(boid.pos.z)) * WALL_FORCE;
+        }
+    }
+
+    for (boid, accel) in model.boids.iter_mut().zip(accels.iter()) {
+        // Update our physics.
+        boid.vel += *accel;
+        boid.vel = boid.vel.clamp_length_max(MAX_SPEED);
+        boid.pos += boid.vel;
+
+        match model.boundary_mode {
+            BoundaryMode::Wrap => {
+                // Come back in the opposite side of the box.
+                let wrap = |coord: f32| (coord + half).rem_euclid(BOX_SIZE) - half;
+                boid.pos = vec3(wrap(boid.pos.x), wrap(boid.pos.y), wrap(boid.pos.z));
+            }
+            BoundaryMode::SoftWalls => {
+                // The walls should turn us around before this, but don't let anyone escape.
+                boid.pos = boid.pos.clamp(Vec3::splat(-half), Vec3::splat(half));
+            }
+        }
+    }
+}
+
+fn view(app: &App, model: &Model, frame: Frame) {
+    let draw = app.draw();
+
+    draw.background().color(Rgb::new(50u8, 50, 50));
+
+    let camera = &model.camera;
+    // Depths of the nearest and farthest points of the box, for fading with distance.
+    let box_radius = (3.0f32).sqrt() * BOX_SIZE / 2.0;
+    let (near, far) = (camera.distance - box_radius, camera.distance + box_radius);
+    let depth_shade = |depth: f32| map_range(clamp(depth, near, far), near, far, 1.0, FAR_BRIGHTNESS);
+
+    // Box edges, between corners that differ in exactly one coordinate.
+    let half = BOX_SIZE / 2.0;
+    let corner = |i: u32| vec3(
+        if i & 1 == 0 { -half } else { half },
+        if i & 2 == 0 { -half } else { half },
+        if i & 4 == 0 { -half } else { half },
+    );
+    for i in 0..8u32 {
+        for bit in [1, 2, 4] {
+            if i & bit != 0 {
+                continue;
+            }
+            if let (Some((a, _)), Some((b, _))) = (camera.project(corner(i)), camera.project(corner(i | bit))) {
+                draw.line()
+                    .start(a)
+                    .end(b)
+                    .color(Rgba::new(1.0, 1.0, 1.0, 0.2));
+            }
+        }
+    }
+
+    for repel in &model.repels {
+        if let Some((pos, depth)) = camera.project(repel.pos) {
+            draw.ellipse()
+                .radius(BOID_RADIUS * FOCAL_LENGTH / depth)
+                .color(nannou::color::RED)
+                .xy(pos);
+        }
+    }
+
+    // Gather every visible face so they can be drawn back to front.
+    let light = Vec3::from(LIGHT_DIR).normalize();
+    let eye = camera.eye();
+    let mut faces = Vec::with_capacity(model.boids.len() * 4);
+    for boid in &model.boids {
+        let corners = boid.corners();
+        let center = corners.iter().sum::<Vec3>() / 4.0;
+        for skip in 0..4 {
+            let mut face = corners.iter().enumerate()
+                .filter(|&(i, _)| i != skip)
+                .map(|(_, &c)| c);
+            let (a, b, c) = (face.next().unwrap(), face.next().unwrap(), face.next().unwrap());
+            let mut normal = (b - a).cross(c - a).normalize();
+            let face_center = (a + b + c) / 3.0;
+            if normal.dot(face_center - center) < 0.0 {
+                normal = -normal;
+            }
+            // Skip faces pointing away from the camera.
+            if normal.dot(eye - face_center) <= 0.0 {
+                continue;
+            }
+            // Faces are sorted by the depth of their middle rather than of any one corner.
+            let projected = (camera.project(a), camera.project(b), camera.project(c), camera.project(face_center));
+            if let (Some((pa, _)), Some((pb, _)), Some((pc, _)), Some((_, depth))) = projected {
+                let lit = AMBIENT_LIGHT + (1.0 - AMBIENT_LIGHT) * normal.dot(light).max(0.0);
+                faces.push((depth, [pa, pb, pc], lit * depth_shade(depth)));
+            }
+        }
+    }
+    faces.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+
+    for (_, [a, b, c], brightness) in faces {
+        draw.tri()
+            .points(a, b, c)
+            .color(Rgb::new(95.0 / 255.0 * brightness, 219.0 / 255.0 * brightness, 0.0));
+    }
+
+    draw.to_frame(app, &frame)
+        .unwrap();
+}
+
+fn main() {
+    nannou::app(model)
+        .update(update)
+        .run();
+}
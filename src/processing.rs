@@ -0,0 +1,316 @@
+// A Processing-style drawing state on top of nannou's Draw, for porting sketches like the ones in
+// inspiration/. Set fill, stroke and transforms once and every shape after picks them up, instead
+// of restating them on each builder.
+//
+// Colors are given the way Processing takes them: a gray level, gray and alpha, three channels or
+// three channels and alpha, scaled by the current color mode's ranges. So `fill(200, 100)` becomes
+// `p.fill((200.0, 100.0))`.
+
+use nannou::color::{IntoLinSrgba, LinSrgba};
+use nannou::geom::Rect;
+use nannou::prelude::*;
+use nannou::Draw;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorMode {
+    Rgb,
+    Hsb,
+}
+
+// How the position and size of rect() and ellipse() are interpreted. Same as Processing's.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShapeMode {
+    // (x, y) is the top left corner, followed by the width and height.
+    Corner,
+    // (x, y) and (w, h) are two opposite corners.
+    Corners,
+    // (x, y) is the center, followed by the width and height.
+    Center,
+    // (x, y) is the center, followed by half the width and height.
+    Radius,
+}
+
+// The ways Processing's fill(), stroke() and background() can be called.
+pub enum ColorArgs {
+    Gray(f32, Option<f32>),
+    Channels(f32, f32, f32, Option<f32>),
+    // Already a color, so the color mode doesn't apply.
+    Color(LinSrgba),
+}
+
+impl From<f32> for ColorArgs {
+    fn from(gray: f32) -> Self {
+        ColorArgs::Gray(gray, None)
+    }
+}
+
+impl From<(f32, f32)> for ColorArgs {
+    fn from((gray, alpha): (f32, f32)) -> Self {
+        ColorArgs::Gray(gray, Some(alpha))
+    }
+}
+
+impl From<(f32, f32, f32)> for ColorArgs {
+    fn from((v1, v2, v3): (f32, f32, f32)) -> Self {
+        ColorArgs::Channels(v1, v2, v3, None)
+    }
+}
+
+impl From<(f32, f32, f32, f32)> for ColorArgs {
+    fn from((v1, v2, v3, alpha): (f32, f32, f32, f32)) -> Self {
+        ColorArgs::Channels(v1, v2, v3, Some(alpha))
+    }
+}
+
+impl From<Rgba> for ColorArgs {
+    fn from(color: Rgba) -> Self {
+        ColorArgs::Color(color.into_lin_srgba())
+    }
+}
+
+impl From<Rgb> for ColorArgs {
+    fn from(color: Rgb) -> Self {
+        ColorArgs::Color(color.into_lin_srgba())
+    }
+}
+
+// Everything push() saves.
+#[derive(Clone)]
+struct State {
+    // Carries the current transform.
+    draw: Draw,
+    fill: Option<LinSrgba>,
+    stroke: Option<LinSrgba>,
+    stroke_weight: f32,
+    color_mode: ColorMode,
+    // The maximum of each channel, then alpha.
+    color_range: [f32; 4],
+    rect_mode: ShapeMode,
+    ellipse_mode: ShapeMode,
+}
+
+pub struct Processing {
+    state: State,
+    // Saved by push() and push_matrix(), along with whether to only restore the transform.
+    stack: Vec<(State, bool)>,
+}
+
+impl Processing {
+    // Draws in nannou's coordinates: the origin in the middle of the window and y pointing up.
+    pub fn new(draw: &Draw) -> Self {
+        Self {
+            state: State {
+                draw: draw.clone(),
+                fill: Some(lin_srgba(1.0, 1.0, 1.0, 1.0)),
+                stroke: Some(lin_srgba(0.0, 0.0, 0.0, 1.0)),
+                stroke_weight: 1.0,
+                color_mode: ColorMode::Rgb,
+                color_range: [255.0; 4],
+                rect_mode: ShapeMode::Corner,
+                ellipse_mode: ShapeMode::Center,
+            },
+            stack: Vec::new(),
+        }
+    }
+
+    // Draws in Processing's coordinates: the origin at the top left of rect and y pointing down.
+    // Rotations go clockwise on screen, like they do in Processing.
+    pub fn with_screen_coords(draw: &Draw, rect: Rect) -> Self {
+        let draw = draw
+            .x_y(rect.left(), rect.top())
+            .scale_axes(vec3(1.0, -1.0, 1.0));
+        Self::new(&draw)
+    }
+
+    // The underlying Draw with the current transform, for anything not covered here.
+    pub fn draw(&self) -> &Draw {
+        &self.state.draw
+    }
+
+    pub fn color_mode(&mut self, mode: ColorMode) {
+        self.state.color_mode = mode;
+    }
+
+    // Like colorMode(mode, max1, max2, max3, maxA).
+    pub fn color_range(&mut self, max1: f32, max2: f32, max3: f32, max_alpha: f32) {
+        self.state.color_range = [max1, max2, max3, max_alpha];
+    }
+
+    // Turns color arguments into a color using the current color mode.
+    pub fn color(&self, args: impl Into<ColorArgs>) -> LinSrgba {
+        let [max1, max2, max3, max_alpha] = self.state.color_range;
+        let alpha = |a: Option<f32>| a.map_or(1.0, |a| a / max_alpha);
+        match args.into() {
+            ColorArgs::Gray(gray, a) => {
+                let v = gray / max3;
+                srgba(v, v, v, alpha(a)).into_lin_srgba()
+            }
+            ColorArgs::Channels(v1, v2, v3, a) => match self.state.color_mode {
+                ColorMode::Rgb => srgba(v1 / max1, v2 / max2, v3 / max3, alpha(a)).into_lin_srgba(),
+                ColorMode::Hsb => hsva(v1 / max1, v2 / max2, v3 / max3, alpha(a)).into_lin_srgba(),
+            },
+            ColorArgs::Color(color) => color,
+        }
+    }
+
+    pub fn background(&self, args: impl Into<ColorArgs>) {
+        let color = self.color(args);
+        self.state.draw.background().color(color);
+    }
+
+    pub fn fill(&mut self, args: impl Into<ColorArgs>) {
+        self.state.fill = Some(self.color(args));
+    }
+
+    pub fn no_fill(&mut self) {
+        self.state.fill = None;
+    }
+
+    pub fn stroke(&mut self, args: impl Into<ColorArgs>) {
+        self.state.stroke = Some(self.color(args));
+    }
+
+    pub fn no_stroke(&mut self) {
+        self.state.stroke = None;
+    }
+
+    pub fn stroke_weight(&mut self, weight: f32) {
+        self.state.stroke_weight = weight;
+    }
+
+    pub fn rect_mode(&mut self, mode: ShapeMode) {
+        self.state.rect_mode = mode;
+    }
+
+    pub fn ellipse_mode(&mut self, mode: ShapeMode) {
+        self.state.ellipse_mode = mode;
+    }
+
+    // Saves the transform and style, like push().
+    pub fn push(&mut self) {
+        self.stack.push((self.state.clone(), false));
+    }
+
+    // Saves only the transform, like pushMatrix().
+    pub fn push_matrix(&mut self) {
+        self.stack.push((self.state.clone(), true));
+    }
+
+    // Restores the last push() or push_matrix(). Popping with nothing pushed is ignored.
+    pub fn pop(&mut self) {
+        match self.stack.pop() {
+            Some((state, true)) => self.state.draw = state.draw,
+            Some((state, false)) => self.state = state,
+            None => {}
+        }
+    }
+
+    // Restores the last push_matrix(). Like Processing, it's an error to use it to undo a push().
+    pub fn pop_matrix(&mut self) {
+        if let Some((_, false)) = self.stack.last() {
+            panic!("pop_matrix() called to undo a push(), use pop() instead");
+        }
+        self.pop();
+    }
+
+    pub fn translate(&mut self, x: f32, y: f32) {
+        self.state.draw = self.state.draw.x_y(x, y);
+    }
+
+    // In radians.
+    pub fn rotate(&mut self, angle: f32) {
+        self.state.draw = self.state.draw.rotate(angle);
+    }
+
+    pub fn scale(&mut self, s: f32) {
+        self.state.draw = self.state.draw.scale(s);
+    }
+
+    pub fn ellipse(&self, x: f32, y: f32, w: f32, h: f32) {
+        let rect = shape_rect(self.state.ellipse_mode, x, y, w, h);
+        let state = &self.state;
+        if let Some(fill) = state.fill {
+            state.draw.ellipse()
+                .xy(rect.xy())
+                .wh(rect.wh())
+                .color(fill);
+        }
+        if let Some(stroke) = state.stroke {
+            state.draw.ellipse()
+                .xy(rect.xy())
+                .wh(rect.wh())
+                .no_fill()
+                .stroke(stroke)
+                .stroke_weight(state.stroke_weight);
+        }
+    }
+
+    pub fn circle(&self, x: f32, y: f32, diameter: f32) {
+        self.ellipse(x, y, diameter, diameter);
+    }
+
+    pub fn rect(&self, x: f32, y: f32, w: f32, h: f32) {
+        let rect = shape_rect(self.state.rect_mode, x, y, w, h);
+        self.polygon(&[
+            rect.bottom_left(),
+            rect.bottom_right(),
+            rect.top_right(),
+            rect.top_left(),
+        ]);
+    }
+
+    pub fn triangle(&self, x1: f32, y1: f32, x2: f32, y2: f32, x3: f32, y3: f32) {
+        self.polygon(&[vec2(x1, y1), vec2(x2, y2), vec2(x3, y3)]);
+    }
+
+    // Like beginShape(), a vertex() per point and endShape(CLOSE).
+    pub fn polygon(&self, points: &[Vec2]) {
+        let state = &self.state;
+        if let Some(fill) = state.fill {
+            state.draw.polygon()
+                .color(fill)
+                .points(points.iter().copied());
+        }
+        if let Some(stroke) = state.stroke {
+            state.draw.polygon()
+                .no_fill()
+                .stroke(stroke)
+                .stroke_weight(state.stroke_weight)
+                .points(points.iter().copied());
+        }
+    }
+
+    // Lines only use the stroke.
+    pub fn line(&self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        let state = &self.state;
+        if let Some(stroke) = state.stroke {
+            state.draw.line()
+                .start(vec2(x1, y1))
+                .end(vec2(x2, y2))
+                .color(stroke)
+                .weight(state.stroke_weight);
+        }
+    }
+
+    // A dot as wide as the stroke weight, in the stroke color.
+    pub fn point(&self, x: f32, y: f32) {
+        let state = &self.state;
+        if let Some(stroke) = state.stroke {
+            state.draw.ellipse()
+                .x_y(x, y)
+                .radius(state.stroke_weight / 2.0)
+                .color(stroke);
+        }
+    }
+}
+
+// The rect covered by a shape given Processing-style arguments. The result is the same whichever
+// way y points.
+fn shape_rect(mode: ShapeMode, x: f32, y: f32, w: f32, h: f32) -> Rect {
+    match mode {
+        ShapeMode::Corner => Rect::from_corners(pt2(x, y), pt2(x + w, y + h)),
+        ShapeMode::Corners => Rect::from_corners(pt2(x, y), pt2(w, h)),
+        ShapeMode::Center => Rect::from_x_y_w_h(x, y, w, h),
+        ShapeMode::Radius => Rect::from_x_y_w_h(x, y, w * 2.0, h * 2.0),
+    }
+}
@@ -0,0 +1,86 @@
+// Bloom for 008_lightning. The scene is blurred horizontally into a half size texture, that's
+// blurred vertically into another one, and the result is added back over the scene.
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[block]]
+struct BlurUniforms {
+    // One texel of the target along the blur direction, in uv units.
+    direction: vec2<f32>;
+    // Standard deviation in texels.
+    sigma: f32;
+    pad: f32;
+};
+
+[[block]]
+struct CompositeUniforms {
+    // Multiplies the bloom. White for no tint.
+    tint: vec4<f32>;
+    strength: f32;
+    // Added to everything for the flash after a strike.
+    flash: f32;
+    pad0: f32;
+    pad1: f32;
+};
+
+[[group(0), binding(0)]]
+var blur_source: texture_2d<f32>;
+[[group(0), binding(1)]]
+var blur_sampler: sampler;
+[[group(0), binding(2)]]
+var<uniform> blur: BlurUniforms;
+
+// The composite pass has its own bind group layout, numbered after the blur's so the two don't
+// clash in this module.
+[[group(0), binding(3)]]
+var scene_texture: texture_2d<f32>;
+[[group(0), binding(4)]]
+var bloom_texture: texture_2d<f32>;
+[[group(0), binding(5)]]
+var composite_sampler: sampler;
+[[group(0), binding(6)]]
+var<uniform> composite: CompositeUniforms;
+
+// One triangle that covers the whole target.
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> VertexOutput {
+    let corner = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var output: VertexOutput;
+    output.position = vec4<f32>(corner * 2.0 - vec2<f32>(1.0), 0.0, 1.0);
+    output.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return output;
+}
+
+[[stage(fragment)]]
+fn fs_blur(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Three standard deviations covers nearly all of the curve.
+    let radius = min(i32(ceil(blur.sigma * 3.0)), 64);
+    var sum: vec4<f32> = vec4<f32>(0.0);
+    var total: f32 = 0.0;
+    var i: i32 = -radius;
+    loop {
+        if (i > radius) {
+            break;
+        }
+        let x = f32(i);
+        let weight = exp(-x * x / (2.0 * blur.sigma * blur.sigma));
+        sum = sum + textureSample(blur_source, blur_sampler, input.uv + blur.direction * x) * weight;
+        total = total + weight;
+        continuing {
+            i = i + 1;
+        }
+    }
+    return sum / total;
+}
+
+[[stage(fragment)]]
+fn fs_composite(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let scene = textureSample(scene_texture, composite_sampler, input.uv).rgb;
+    let bloom = textureSample(bloom_texture, composite_sampler, input.uv).rgb;
+    let color = scene + bloom * composite.strength * composite.tint.rgb + vec3<f32>(composite.flash);
+    // Roll bright cores off to white instead of clipping them.
+    return vec4<f32>(vec3<f32>(1.0) - exp(-color), 1.0);
+}
@@ -0,0 +1,147 @@
+// Computes the wind field for 011_noise_wind on the GPU, one invocation per grid cell.
+//
+// The noise is a hash-based take on Perlin's improved noise rather than a port of the `noise`
+// crate, so fields look alike but aren't identical to the CPU ones.
+
+[[block]]
+struct Uniforms {
+    grid_width: u32;
+    grid_height: u32;
+    // 0 for angle, 1 for curl.
+    field_mode: u32;
+    // 0 for plain Perlin, 1 for fBm, 2 for ridged multifractal.
+    fractal: u32;
+    octaves: u32;
+    seed: u32;
+    frequency: f32;
+    // Already multiplied by the time scale.
+    time: f32;
+    lacunarity: f32;
+    persistence: f32;
+    warp: f32;
+    curl_epsilon: f32;
+};
+
+[[block]]
+struct Field {
+    cells: array<vec2<f32>>;
+};
+
+[[group(0), binding(0)]]
+var<storage, read_write> field: Field;
+
+[[group(0), binding(1)]]
+var<uniform> uniforms: Uniforms;
+
+fn hash(x: i32, y: i32, z: i32) -> u32 {
+    var h: u32 = uniforms.seed * 2654435761u;
+    h = (h ^ u32(x)) * 2246822519u;
+    h = (h ^ u32(y)) * 3266489917u;
+    h = (h ^ u32(z)) * 668265263u;
+    return h ^ (h >> 15u);
+}
+
+// Dot product of (x, y, z) with one of Perlin's twelve gradient directions.
+fn grad(h: u32, x: f32, y: f32, z: f32) -> f32 {
+    let k = h & 15u;
+    let u = select(y, x, k < 8u);
+    let v = select(select(z, x, k == 12u || k == 14u), y, k < 4u);
+    return select(-u, u, (k & 1u) == 0u) + select(-v, v, (k & 2u) == 0u);
+}
+
+fn fade(t: f32) -> f32 {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+fn perlin(p: vec3<f32>) -> f32 {
+    let cell = floor(p);
+    let f = p - cell;
+    let x = i32(cell.x);
+    let y = i32(cell.y);
+    let z = i32(cell.z);
+    let u = fade(f.x);
+    let v = fade(f.y);
+    let w = fade(f.z);
+
+    let n000 = grad(hash(x, y, z), f.x, f.y, f.z);
+    let n100 = grad(hash(x + 1, y, z), f.x - 1.0, f.y, f.z);
+    let n010 = grad(hash(x, y + 1, z), f.x, f.y - 1.0, f.z);
+    let n110 = grad(hash(x + 1, y + 1, z), f.x - 1.0, f.y - 1.0, f.z);
+    let n001 = grad(hash(x, y, z + 1), f.x, f.y, f.z - 1.0);
+    let n101 = grad(hash(x + 1, y, z + 1), f.x - 1.0, f.y, f.z - 1.0);
+    let n011 = grad(hash(x, y + 1, z + 1), f.x, f.y - 1.0, f.z - 1.0);
+    let n111 = grad(hash(x + 1, y + 1, z + 1), f.x - 1.0, f.y - 1.0, f.z - 1.0);
+
+    let near = mix(mix(n000, n100, u), mix(n010, n110, u), v);
+    let far = mix(mix(n001, n101, u), mix(n011, n111, u), v);
+    return mix(near, far, w);
+}
+
+fn fractal(p: vec3<f32>) -> f32 {
+    if (uniforms.fractal == 0u) {
+        return perlin(p);
+    }
+
+    var sum: f32 = 0.0;
+    var total: f32 = 0.0;
+    var amplitude: f32 = 1.0;
+    var q: vec3<f32> = p;
+    var i: u32 = 0u;
+    loop {
+        if (i >= uniforms.octaves) {
+            break;
+        }
+        var n: f32 = perlin(q);
+        if (uniforms.fractal == 2u) {
+            // Fold the noise into sharp ridges.
+            n = 1.0 - abs(n);
+            n = n * n * 2.0 - 1.0;
+        }
+        sum = sum + n * amplitude;
+        total = total + amplitude;
+        amplitude = amplitude * uniforms.persistence;
+        q = q * uniforms.lacunarity;
+
+        continuing {
+            i = i + 1u;
+        }
+    }
+    return sum / total;
+}
+
+// Noise at grid coordinates (x, y), with optional domain warping.
+fn field_noise(x: f32, y: f32) -> f32 {
+    var px: f32 = x * uniforms.frequency;
+    var py: f32 = y * uniforms.frequency;
+    let z = uniforms.time;
+    if (uniforms.warp != 0.0) {
+        let qx = fractal(vec3<f32>(px + 5.2, py + 1.3, z));
+        let qy = fractal(vec3<f32>(px + 1.7, py + 9.2, z));
+        px = px + uniforms.warp * qx;
+        py = py + uniforms.warp * qy;
+    }
+    return fractal(vec3<f32>(px, py, z));
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    if (id.x >= uniforms.grid_width || id.y >= uniforms.grid_height) {
+        return;
+    }
+
+    let x = f32(id.x);
+    let y = f32(id.y);
+    var vel: vec2<f32>;
+    if (uniforms.field_mode == 0u) {
+        let value = field_noise(x, y);
+        let angle = (value + 1.0) * 3.14159265;
+        vel = vec2<f32>(cos(angle), sin(angle)) * ((value + 1.0) * 0.5);
+    } else {
+        // Curl is normalized on the CPU once the whole field is back.
+        let e = uniforms.curl_epsilon;
+        let dx = (field_noise(x + e, y) - field_noise(x - e, y)) / (2.0 * e);
+        let dy = (field_noise(x, y + e) - field_noise(x, y - e)) / (2.0 * e);
+        vel = vec2<f32>(-dy, -dx);
+    }
+    field.cells[id.y * uniforms.grid_width + id.x] = vel;
+}